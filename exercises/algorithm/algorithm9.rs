@@ -1,27 +1,134 @@
-use std::cmp::Ord;
-use std::default::Default;
+use std::cmp::{Ord, Ordering};
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FusedIterator;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
 
-pub struct Heap<T>
-where
-    T: Default,
-{
+pub struct Heap<T> {
     count: usize,
     items: Vec<T>,
-    comparator: fn(&T, &T) -> bool,
+    comparator: Comparator<T>,
 }
 
-impl<T> Heap<T>
-where
-    T: Default,
-{
+// `Send + Sync` keeps `Heap<T>` shareable across threads whenever `T` is, so
+// closure-ordered heaps still work behind `Arc<Mutex<...>>`
+type SharedComparator<T> = Arc<dyn Fn(&T, &T) -> bool + Send + Sync>;
+
+/// Decides whether the first argument belongs above the second
+enum Comparator<T> {
+    Fn(fn(&T, &T) -> bool),
+    Closure(SharedComparator<T>),
+    // `Less` means the first argument belongs above the second
+    Ordering(fn(&T, &T) -> Ordering),
+    Reversed(Box<Comparator<T>>),
+}
+
+impl<T> Comparator<T> {
+    fn call(&self, a: &T, b: &T) -> bool {
+        match self {
+            Comparator::Fn(f) => f(a, b),
+            Comparator::Closure(f) => f(a, b),
+            Comparator::Ordering(f) => f(a, b) == Ordering::Less,
+            Comparator::Reversed(inner) => inner.call(b, a),
+        }
+    }
+}
+
+impl<T> Clone for Comparator<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Comparator::Fn(f) => Comparator::Fn(*f),
+            Comparator::Closure(f) => Comparator::Closure(Arc::clone(f)),
+            Comparator::Ordering(f) => Comparator::Ordering(*f),
+            Comparator::Reversed(inner) => Comparator::Reversed(inner.clone()),
+        }
+    }
+}
+
+impl<T> Heap<T> {
     pub fn new(comparator: fn(&T, &T) -> bool) -> Self {
         Self {
             count: 0,
+            items: Vec::new(),
+            comparator: Comparator::Fn(comparator),
+        }
+    }
+
+    /// Create a heap ordered by a closure, which may capture runtime state
+    pub fn new_by<F>(comparator: F) -> Self
+    where
+        F: Fn(&T, &T) -> bool + Send + Sync + 'static,
+    {
+        Self {
+            count: 0,
+            items: Vec::new(),
+            comparator: Comparator::Closure(Arc::new(comparator)),
+        }
+    }
+
+    /// Create a heap from a three-way comparison; elements comparing `Less`
+    /// are popped first, so `|a, b| a.cmp(b)` gives a MinHeap
+    pub fn new_by_ord(cmp: fn(&T, &T) -> Ordering) -> Self {
+        Self {
+            count: 0,
+            items: Vec::new(),
+            comparator: Comparator::Ordering(cmp),
+        }
+    }
+
+    /// Create a MinHeap for partially ordered values such as floats. Values
+    /// not equal to themselves (`NaN`) rank greatest, so they pop last and
+    /// never break the heap invariant.
+    pub fn new_total() -> Self
+    where
+        T: PartialOrd,
+    {
+        Self::new(partial_less::<T>)
+    }
+
+    /// Create a heap whose comparator may fail. A failed comparison aborts
+    /// the operation and leaves the heap unchanged.
+    pub fn new_try<E>(comparator: fn(&T, &T) -> Result<bool, E>) -> TryHeap<T, E> {
+        TryHeap {
             items: Vec::new(),
             comparator,
         }
     }
 
+    /// Create an empty heap with room for `capacity` elements
+    pub fn with_capacity(capacity: usize, comparator: fn(&T, &T) -> bool) -> Self {
+        Self {
+            count: 0,
+            items: Vec::with_capacity(capacity),
+            comparator: Comparator::Fn(comparator),
+        }
+    }
+
+    /// Build a heap from an existing vector in O(n)
+    pub fn from_vec(items: Vec<T>, comparator: fn(&T, &T) -> bool) -> Self {
+        let mut heap = Self {
+            count: items.len(),
+            items,
+            comparator: Comparator::Fn(comparator),
+        };
+        heap.debug_check_comparator(0);
+        heap.build_heap();
+        heap
+    }
+
+    /// Build a heap holding just `value`
+    pub fn singleton(value: T, comparator: fn(&T, &T) -> bool) -> Self {
+        Self::from_vec(vec![value], comparator)
+    }
+
+    /// Build a heap holding `a` and `b`
+    pub fn pair(a: T, b: T, comparator: fn(&T, &T) -> bool) -> Self {
+        Self::from_vec(vec![a, b], comparator)
+    }
+
     pub fn len(&self) -> usize {
         self.count
     }
@@ -30,10 +137,555 @@ where
         self.len() == 0
     }
 
+    /// Install a vector already sorted in comparator order without any sift
+    /// work. The caller guarantees sortedness; debug builds check only the
+    /// weaker heap property, which is all the installed layout relies on.
+    pub fn from_sorted_vec(sorted: Vec<T>, comparator: fn(&T, &T) -> bool) -> Self {
+        let heap = Self {
+            count: sorted.len(),
+            items: sorted,
+            comparator: Comparator::Fn(comparator),
+        };
+        debug_assert!(
+            heap.is_valid(),
+            "from_sorted_vec given a vector violating the heap property"
+        );
+        heap
+    }
+
+    /// Number of elements the heap can hold without reallocating
+    pub fn capacity(&self) -> usize {
+        self.items.capacity()
+    }
+
+    /// Reserve room for at least `additional` more elements
+    pub fn reserve(&mut self, additional: usize) {
+        self.items.reserve(additional);
+    }
+
+    /// Reserve room for exactly `additional` more elements
+    pub fn reserve_exact(&mut self, additional: usize) {
+        self.items.reserve_exact(additional);
+    }
+
+    /// Flip the ordering, turning a min-heap into a max-heap and vice versa,
+    /// then re-heapify in O(n)
+    pub fn reverse_order(&mut self) {
+        let placeholder = Comparator::Fn(|_: &T, _: &T| false);
+        self.comparator = match std::mem::replace(&mut self.comparator, placeholder) {
+            Comparator::Reversed(inner) => *inner,
+            other => Comparator::Reversed(Box::new(other)),
+        };
+        self.build_heap();
+        self.debug_check_len();
+    }
+
+    /// Release spare capacity, e.g. after draining most of a large heap
+    pub fn shrink_to_fit(&mut self) {
+        self.items.truncate(self.count);
+        self.items.shrink_to_fit();
+        self.debug_check_len();
+    }
+
+    /// Release spare capacity but keep room for at least `min_capacity`
+    /// elements
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        self.items.truncate(self.count);
+        self.items.shrink_to(min_capacity);
+        self.debug_check_len();
+    }
+
     pub fn add(&mut self, value: T) {
         self.items.push(value);
         self.count += 1;
         self.bubble_up(self.count - 1);
+        self.debug_check_len();
+    }
+
+    /// Append many values, then restore the heap in one bottom-up pass over
+    /// the ancestors of the new elements rather than sifting each one up
+    pub fn add_all(&mut self, values: impl IntoIterator<Item = T>) {
+        let start = self.count;
+        self.items.extend(values);
+        self.count = self.items.len();
+        self.debug_check_comparator(start);
+        self.build_heap_from(start);
+        self.debug_check_len();
+    }
+
+    /// Check the heap property: no child out-ranks its parent
+    pub fn is_valid(&self) -> bool {
+        (1..self.count).all(|idx| {
+            !self
+                .comparator
+                .call(&self.items[idx], &self.items[self.parent_idx(idx)])
+        })
+    }
+
+    /// Remove and return the top of the heap
+    pub fn pop(&mut self) -> Option<T> {
+        if self.count == 0 {
+            None
+        } else {
+            let top = self.items.swap_remove(0);
+            self.count -= 1;
+            self.bubble_down(0);
+            self.debug_check_len();
+            Some(top)
+        }
+    }
+
+    /// Remove the first element equal to `value`, returning whether one was
+    /// found
+    pub fn remove(&mut self, value: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        // Path syntax, since `self.position` would find `Iterator::position`
+        match Heap::position(self, value) {
+            Some(idx) => self.remove_at(idx).is_some(),
+            None => false,
+        }
+    }
+
+    /// Remove the element at array index `index`, e.g. one found with
+    /// `position`, and repair the heap around it
+    pub fn remove_at(&mut self, index: usize) -> Option<T> {
+        if index >= self.count {
+            return None;
+        }
+        let removed = self.items.swap_remove(index);
+        self.count -= 1;
+        // The element moved into `index` may out-rank its parent or be
+        // out-ranked by its children, so sift both ways
+        if index < self.count {
+            self.bubble_up(index);
+            self.bubble_down(index);
+        }
+        self.debug_check_len();
+        Some(removed)
+    }
+
+    /// Pop the top and push `value` with a single sift. On an empty heap this
+    /// just adds `value` and returns `None`.
+    pub fn replace(&mut self, value: T) -> Option<T> {
+        if self.count == 0 {
+            self.add(value);
+            None
+        } else {
+            Some(self.replace_root(value))
+        }
+    }
+
+    /// `replace` for a heap the caller knows is non-empty, without handling
+    /// the empty case
+    pub fn replace_root(&mut self, value: T) -> T {
+        debug_assert!(self.count > 0, "replace_root on an empty heap");
+        let top = std::mem::replace(&mut self.items[0], value);
+        self.bubble_down(0);
+        self.debug_check_len();
+        top
+    }
+
+    /// Push `value` and pop the top in one step. If `value` would itself be
+    /// the new top it is returned straight away without touching storage.
+    pub fn push_pop(&mut self, value: T) -> T {
+        if self.count > 0 && self.comparator.call(&self.items[0], &value) {
+            self.replace_root(value)
+        } else {
+            value
+        }
+    }
+
+    /// Return the top of the heap without removing it
+    pub fn peek(&self) -> Option<&T> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(&self.items[0])
+        }
+    }
+
+    /// The element that would be popped next; an alias of `peek`. O(1).
+    pub fn first(&self) -> Option<&T> {
+        self.peek()
+    }
+
+    /// An element ranking lowest under the comparator (the max of a
+    /// MinHeap), found by an O(n) scan of the leaves. Among equal elements
+    /// it may differ from the one `last()` drains to.
+    pub fn max_element(&self) -> Option<&T> {
+        self.last_idx().map(|idx| &self.items[idx])
+    }
+
+    /// The element that would be popped last. A heap keeps no cheap `last`:
+    /// this drains a clone, O(n log n). `max_element` is the O(n) scan when
+    /// any lowest-ranking element will do.
+    pub fn find_last(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.clone().last()
+    }
+
+    /// Pop the top only if `pred` accepts it; otherwise leave the heap as is
+    pub fn pop_if<F: FnOnce(&T) -> bool>(&mut self, pred: F) -> Option<T> {
+        match self.peek() {
+            Some(top) if pred(top) => self.pop(),
+            _ => None,
+        }
+    }
+
+    /// The top two elements in pop order, in O(1): the runner-up is always
+    /// the better of the root's children
+    pub fn peek_two(&self) -> (Option<&T>, Option<&T>) {
+        let second = match self.count {
+            0 | 1 => None,
+            2 => Some(&self.items[1]),
+            _ if self.comparator.call(&self.items[2], &self.items[1]) => Some(&self.items[2]),
+            _ => Some(&self.items[1]),
+        };
+        (self.peek(), second)
+    }
+
+    /// Return the `n`-th element in pop order (0 is the top) without
+    /// modifying the heap. Walks the heap best-first, costing O(n log n).
+    pub fn peek_nth(&self, n: usize) -> Option<&T> {
+        if n >= self.count {
+            return None;
+        }
+        let higher = |a: &usize, b: &usize| self.comparator.call(&self.items[*a], &self.items[*b]);
+        // Indices that could come next in pop order, kept as a heap themselves
+        let mut frontier = vec![0];
+        for _ in 0..n {
+            let top = frontier.swap_remove(0);
+            sift_down(&mut frontier, 0, &higher);
+            for child in [self.left_child_idx(top), self.right_child_idx(top)] {
+                if child < self.count {
+                    frontier.push(child);
+                    let last = frontier.len() - 1;
+                    sift_up(&mut frontier, last, &higher);
+                }
+            }
+        }
+        Some(&self.items[frontier[0]])
+    }
+
+    /// Raw array slot at `index`, for asserting layouts in tests. Slot 0 is
+    /// the top; other slots follow no sorted order.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.items[..self.count].get(index)
+    }
+
+    /// The live elements in internal array order, NOT sorted order
+    pub fn as_slice(&self) -> &[T] {
+        &self.items[..self.count]
+    }
+
+    /// Render the array layout as a tree, one node per line with children
+    /// indented beneath their parent. An empty heap renders as "".
+    pub fn to_tree_string(&self) -> String
+    where
+        T: fmt::Display,
+    {
+        let mut out = String::new();
+        // Pre-order walk; push the right child first so the left prints first
+        let mut stack = vec![(0, 0)];
+        while let Some((idx, depth)) = stack.pop() {
+            if idx >= self.count {
+                continue;
+            }
+            out.push_str(&format!("{:indent$}{}\n", "", self.items[idx], indent = depth * 2));
+            stack.push((self.right_child_idx(idx), depth + 1));
+            stack.push((self.left_child_idx(idx), depth + 1));
+        }
+        out
+    }
+
+    /// Iterate over the elements in internal array order, NOT sorted order
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.items[..self.count].iter()
+    }
+
+    /// Keep only the elements matching `f`, then rebuild in O(n)
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, f: F) {
+        self.items.retain(f);
+        self.count = self.items.len();
+        self.build_heap();
+        self.debug_check_len();
+    }
+
+    /// Keep the elements matching `pred` and return the rest, sorted in
+    /// comparator order
+    pub fn retain_removed<F: FnMut(&T) -> bool>(&mut self, mut pred: F) -> Vec<T> {
+        let removed = self.extract_if(|x| !pred(x));
+        let mut removed = Heap {
+            count: removed.len(),
+            items: removed,
+            comparator: self.comparator.clone(),
+        };
+        removed.build_heap();
+        self.debug_check_len();
+        removed.into_sorted_vec()
+    }
+
+    /// Remove and return every element matching `pred`, in no particular
+    /// order, then rebuild the remainder in O(n)
+    pub fn extract_if<F: FnMut(&T) -> bool>(&mut self, mut pred: F) -> Vec<T> {
+        let mut extracted = Vec::new();
+        let mut idx = 0;
+        while idx < self.items.len() {
+            if pred(&self.items[idx]) {
+                extracted.push(self.items.swap_remove(idx));
+            } else {
+                idx += 1;
+            }
+        }
+        if !extracted.is_empty() {
+            self.count = self.items.len();
+            self.build_heap();
+        }
+        self.debug_check_len();
+        extracted
+    }
+
+    /// Linear scan for an element equal to `value`
+    pub fn contains(&self, value: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.items[..self.count].contains(value)
+    }
+
+    /// Array index of the first element equal to `value`.
+    ///
+    /// The name clashes with `Iterator::position`, and through a `&mut Heap`
+    /// method lookup finds the trait method first, which would drain the
+    /// heap. Callers holding `&mut Heap` must write `Heap::position(heap, &v)`.
+    pub fn position(&self, value: &T) -> Option<usize>
+    where
+        T: PartialEq,
+    {
+        self.as_slice().iter().position(|x| x == value)
+    }
+
+    /// Number of elements satisfying `pred`, in O(n) without draining
+    pub fn count_matching<F: Fn(&T) -> bool>(&self, pred: F) -> usize {
+        self.as_slice().iter().filter(|item| pred(item)).count()
+    }
+
+    /// Mutable access to the top; the heap is repaired when the guard drops
+    pub fn peek_mut(&mut self) -> Option<PeekMut<'_, T>> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(PeekMut {
+                heap: self,
+                dirty: false,
+            })
+        }
+    }
+
+    /// Mutable access to every element; the heap is rebuilt in O(n) when the
+    /// guard drops, so any change in ordering is repaired
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut { heap: self }
+    }
+
+    /// Remove every element, keeping the allocated storage for reuse
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.count = 0;
+        self.debug_check_len();
+    }
+
+    /// Remove elements in comparator order while keeping the heap itself.
+    /// Dropping the iterator early leaves the unyielded elements in place
+    /// as a valid heap.
+    pub fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        self.by_ref()
+    }
+
+    /// Like `drain`, but comparator order is part of the contract. Dropping
+    /// the iterator early leaves the unyielded elements as a valid heap.
+    pub fn drain_sorted(&mut self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(move || self.pop())
+    }
+
+    /// Empty the heap into a vector sorted by `key` instead of the heap's own
+    /// comparator
+    pub fn drain_sorted_by_key<K: Ord, F: FnMut(&T) -> K>(&mut self, key: F) -> Vec<T> {
+        self.count = 0;
+        let mut items = std::mem::take(&mut self.items);
+        self.debug_check_len();
+        items.sort_by_key(key);
+        items
+    }
+
+    /// Like `drain_sorted`, but yields only the first of each run of equal
+    /// elements, giving sorted and deduplicated output
+    pub fn drain_dedup(&mut self) -> impl Iterator<Item = T> + '_
+    where
+        T: PartialEq,
+    {
+        std::iter::from_fn(move || {
+            let value = self.pop()?;
+            // Equal elements pop adjacently, so the rest of the run is on top
+            while self.peek() == Some(&value) {
+                self.pop();
+            }
+            Some(value)
+        })
+    }
+
+    /// Move every element of `other` into this heap, leaving `other` empty.
+    /// Both heaps are assumed to order elements the same way; mixing a min
+    /// and a max comparator is the caller's responsibility.
+    pub fn append(&mut self, other: &mut Heap<T>) {
+        self.items.append(&mut other.items);
+        self.count = self.items.len();
+        other.count = 0;
+        self.build_heap();
+        self.debug_check_len();
+        other.debug_check_len();
+    }
+
+    /// Combine two heaps into a new one with a single O(n) heapify. The
+    /// result uses `a`'s comparator; `b`'s is ignored.
+    pub fn meld(mut a: Heap<T>, b: Heap<T>) -> Heap<T> {
+        a.items.truncate(a.count);
+        a.items.extend(b.into_vec());
+        a.count = a.items.len();
+        a.build_heap();
+        a.debug_check_len();
+        a
+    }
+
+    /// Pop up to `n` elements in comparator order
+    pub fn pop_n(&mut self, n: usize) -> Vec<T> {
+        let n = n.min(self.count);
+        let mut items = Vec::with_capacity(n);
+        items.extend(self.drain_sorted().take(n));
+        items
+    }
+
+    /// Pop the first `n` elements into a new heap with the same comparator,
+    /// leaving the rest in `self`
+    pub fn split_off(&mut self, n: usize) -> Heap<T> {
+        let items = self.pop_n(n);
+        // Popped in comparator order, so already a valid heap layout
+        Heap {
+            count: items.len(),
+            items,
+            comparator: self.comparator.clone(),
+        }
+    }
+
+    /// Keep only the `n` highest-priority elements, dropping the rest
+    pub fn truncate(&mut self, n: usize) {
+        if n < self.count {
+            *self = self.split_off(n);
+        }
+        self.debug_check_len();
+    }
+
+    /// The element `n` places from the top in comparator order (the n-th
+    /// smallest for a MinHeap), found by popping from a clone
+    pub fn nth_smallest(&self, n: usize) -> Option<T>
+    where
+        T: Clone,
+    {
+        if n >= self.count {
+            return None;
+        }
+        self.clone().nth(n)
+    }
+
+    /// Consume the heap, returning its elements in comparator order
+    pub fn into_sorted_vec(self) -> Vec<T> {
+        self.into_iter_sorted().collect()
+    }
+
+    // `count` must track `items.len()`; a stale tail past `count` would be
+    // silently ignored by most methods
+    fn debug_check_len(&self) {
+        debug_assert_eq!(
+            self.count,
+            self.items.len(),
+            "count out of sync with storage"
+        );
+    }
+
+    /// Assert the length bookkeeping and the heap property, for tests
+    #[cfg(test)]
+    pub(crate) fn check_consistency(&self) {
+        assert_eq!(
+            self.count,
+            self.items.len(),
+            "count out of sync with storage"
+        );
+        assert!(self.is_valid(), "heap property violated");
+    }
+
+    // In debug builds, spot-check that the comparator is a strict ordering on
+    // a few neighbouring elements from `start` on. A comparator that ranks
+    // two elements above each other silently corrupts the heap otherwise.
+    fn debug_check_comparator(&self, start: usize) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        const SAMPLES: usize = 16;
+        let end = self.count.min(start.saturating_add(SAMPLES + 1));
+        for idx in start..end {
+            let a = &self.items[idx];
+            assert!(
+                !self.comparator.call(a, a),
+                "inconsistent comparator: an element ranks above itself (use `<`, not `<=`)"
+            );
+            if idx + 1 < end {
+                let b = &self.items[idx + 1];
+                assert!(
+                    !(self.comparator.call(a, b) && self.comparator.call(b, a)),
+                    "inconsistent comparator: two elements each rank above the other"
+                );
+            }
+        }
+    }
+
+    // The live elements sorted by `Ord`, independent of layout and comparator
+    fn sorted_copy(&self) -> Vec<T>
+    where
+        T: Ord + Clone,
+    {
+        let mut items = self.items[..self.count].to_vec();
+        items.sort();
+        items
+    }
+
+    /// Consume the heap, returning its storage in heap-array layout, NOT
+    /// sorted order
+    pub fn into_vec(mut self) -> Vec<T> {
+        self.items.truncate(self.count);
+        self.items
+    }
+
+    /// Consume the heap as an iterator guaranteed to yield comparator order
+    pub fn into_iter_sorted(self) -> IntoIterSorted<T> {
+        IntoIterSorted { heap: self }
+    }
+
+    // Index of an element ranking lowest under the comparator. It has no
+    // children, so only the leaves need scanning. Among equal elements this
+    // is not necessarily the one the pop sequence ends on.
+    fn last_idx(&self) -> Option<usize> {
+        let mut last = None;
+        for idx in self.count / 2..self.count {
+            match last {
+                Some(l) if !self.comparator.call(&self.items[l], &self.items[idx]) => {}
+                _ => last = Some(idx),
+            }
+        }
+        last
     }
 
     fn parent_idx(&self, idx: usize) -> usize {
@@ -54,7 +706,7 @@ where
 
         if left < self.count {
             if right < self.count {
-                if (self.comparator)(&self.items[left], &self.items[right]) {
+                if self.comparator.call(&self.items[left], &self.items[right]) {
                     Some(left)
                 } else {
                     Some(right)
@@ -70,7 +722,7 @@ where
     fn bubble_down(&mut self, idx: usize) {
         let mut parent_idx = idx;
         while let Some(child_idx) = self.smallest_child_idx(parent_idx) {
-            if (self.comparator)(&self.items[parent_idx], &self.items[child_idx]) {
+            if self.comparator.call(&self.items[parent_idx], &self.items[child_idx]) {
                 break;
             }
             self.items.swap(parent_idx, child_idx);
@@ -82,7 +734,7 @@ where
         let mut child_idx = idx;
         while child_idx > 0 {
             let parent_idx = self.parent_idx(child_idx);
-            if (self.comparator)(&self.items[child_idx], &self.items[parent_idx]) {
+            if self.comparator.call(&self.items[child_idx], &self.items[parent_idx]) {
                 self.items.swap(child_idx, parent_idx);
                 child_idx = parent_idx;
             } else {
@@ -90,11 +742,50 @@ where
             }
         }
     }
+
+    // Floyd's build-heap restricted to the ancestors of items[start..], the
+    // only subtrees that can be out of order after an append
+    fn build_heap_from(&mut self, start: usize) {
+        if start >= self.count || self.count < 2 {
+            return;
+        }
+        let mut lo = start.max(1);
+        let mut hi = self.count - 1;
+        loop {
+            lo = self.parent_idx(lo);
+            hi = self.parent_idx(hi);
+            for idx in (lo..=hi).rev() {
+                self.bubble_down(idx);
+            }
+            if lo == 0 {
+                break;
+            }
+        }
+    }
+
+    // Floyd's build-heap: sift every non-leaf down, last parent first
+    fn build_heap(&mut self) {
+        for idx in (0..self.count / 2).rev() {
+            self.bubble_down(idx);
+        }
+    }
+}
+
+/// How `Heap::from_vec_parallel_with` splits its input into chunks. The
+/// chunks are subtrees rooted on one level of the heap, so the count is
+/// rounded up to a power of two.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkStrategy {
+    /// One chunk per thread
+    EvenBySize,
+    /// This many chunks, taken from a shared queue by the threads as they
+    /// finish, so uneven chunks balance out
+    FixedCount(usize),
 }
 
 impl<T> Heap<T>
 where
-    T: Default + Ord,
+    T: Ord,
 {
     /// Create a new MinHeap
     pub fn new_min() -> Self {
@@ -105,85 +796,3064 @@ where
     pub fn new_max() -> Self {
         Self::new(|a, b| a > b)
     }
-}
-
-impl<T> Iterator for Heap<T>
-where
-    T: Default,
-{
-    type Item = T;
 
-    fn next(&mut self) -> Option<T> {
-        if self.is_empty() {
-            None
-        } else {
-            let top = self.items.swap_remove(0);
-            self.count -= 1;
-            self.bubble_down(0);
-            Some(top)
-        }
+    /// Build a MaxHeap from a std `BinaryHeap`, reusing its buffer. std's
+    /// heap is a max-heap, so it corresponds to `new_max`.
+    pub fn from_std(bh: BinaryHeap<T>) -> Self {
+        Self::from_vec(bh.into_vec(), |a, b| a > b)
     }
-}
 
-pub struct MinHeap;
+    /// Convert into a std `BinaryHeap`, reusing the buffer. The result is
+    /// always max-ordered, whatever this heap's comparator was.
+    pub fn into_std(self) -> BinaryHeap<T> {
+        BinaryHeap::from(self.items)
+    }
 
-impl MinHeap {
-    pub fn new<T>() -> Heap<T>
+    /// Build a MinHeap on up to `threads` threads. The threads heapify
+    /// disjoint subtrees of the final array, then the calling thread sifts
+    /// down the few nodes above them, so the work stays O(n) as in
+    /// `from_vec`. Inputs too small to be worth a thread are built on the
+    /// calling thread.
+    pub fn from_vec_parallel(items: Vec<T>, threads: usize) -> Self
     where
-        T: Default + Ord,
+        T: Send,
     {
-        Heap::new(|a, b| a < b)
+        Self::from_vec_parallel_with(items, threads, ChunkStrategy::EvenBySize)
     }
-}
-
-pub struct MaxHeap;
 
-impl MaxHeap {
-    pub fn new<T>() -> Heap<T>
+    /// Like `from_vec_parallel`, with `strategy` choosing how many subtrees
+    /// the input is split into. A single chunk, a single thread or a small
+    /// input all fall back to the sequential `from_vec`.
+    pub fn from_vec_parallel_with(items: Vec<T>, threads: usize, strategy: ChunkStrategy) -> Self
     where
-        T: Default + Ord,
+        T: Send,
     {
-        Heap::new(|a, b| a > b)
-    }
-}
+        const MIN_CHUNK: usize = 4096;
+        let len = items.len();
+        let chunk_count = match strategy {
+            ChunkStrategy::EvenBySize => threads.min(len / MIN_CHUNK),
+            ChunkStrategy::FixedCount(n) => n.min(len),
+        };
+        if threads <= 1 || chunk_count <= 1 || len < MIN_CHUNK {
+            return Self::from_vec(items, |a, b| a < b);
+        }
+        // The chunks are the subtrees rooted on one level, so their count is
+        // a power of two, and every root has to exist
+        let mut roots = chunk_count.next_power_of_two();
+        while 2 * roots - 1 > len {
+            roots /= 2;
+        }
+
+        // Each level below the roots is one block per subtree, left to right,
+        // and a subtree's blocks in order form its own heap array
+        let mut items = items.into_iter();
+        let top: Vec<T> = items.by_ref().take(roots - 1).collect();
+        let subtree_len = |root: usize| {
+            let (mut size, mut first, mut width) = (0, root, 1);
+            while first < len {
+                size += width.min(len - first);
+                first = 2 * first + 1;
+                width *= 2;
+            }
+            size
+        };
+        let mut subtrees: Vec<Vec<T>> = (roots - 1..2 * roots - 1)
+            .map(|root| Vec::with_capacity(subtree_len(root)))
+            .collect();
+        let mut block = 1;
+        while items.len() > 0 {
+            for subtree in &mut subtrees {
+                subtree.extend(items.by_ref().take(block));
+            }
+            block *= 2;
+        }
+
+        let queue = Mutex::new(subtrees.iter_mut());
+        thread::scope(|s| {
+            for _ in 0..threads.min(roots) {
+                s.spawn(|| loop {
+                    let next = queue.lock().unwrap().next();
+                    match next {
+                        Some(subtree) => heapify(subtree, |a, b| a < b),
+                        None => break,
+                    }
+                });
+            }
+        });
+
+        let mut heap = Self::new_min();
+        heap.items.reserve_exact(len);
+        heap.items.extend(top);
+        let mut subtrees: Vec<_> = subtrees.into_iter().map(Vec::into_iter).collect();
+        let mut block = 1;
+        while heap.items.len() < len {
+            for subtree in &mut subtrees {
+                heap.items.extend(subtree.by_ref().take(block));
+            }
+            block *= 2;
+        }
+        heap.count = len;
+        for idx in (0..roots - 1).rev() {
+            heap.bubble_down(idx);
+        }
+        heap.debug_check_len();
+        heap
+    }
+}
+
+impl<T> Iterator for Heap<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.count, Some(self.count))
+    }
+}
+
+impl<T> ExactSizeIterator for Heap<T> {}
+
+impl<T> FusedIterator for Heap<T> {}
+
+/// Borrowing iteration in internal array order, NOT sorted order
+impl<'a, T> IntoIterator for &'a Heap<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator returned by `Heap::into_iter_sorted`. Unlike iterating the heap
+/// directly, comparator order is part of its contract.
+pub struct IntoIterSorted<T> {
+    heap: Heap<T>,
+}
+
+impl<T> Iterator for IntoIterSorted<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.heap.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.count, Some(self.heap.count))
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIterSorted<T> {}
+
+impl<T> FusedIterator for IntoIterSorted<T> {}
+
+// `extend_one` and `extend_reserve` are still unstable, so `extend` reserving
+// from the size hint up front is the only preallocation hook
+impl<T> Extend<T> for Heap<T> {
+    /// Push every item, then rebuild once instead of sifting each one up
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.items.reserve(iter.size_hint().0);
+        self.items.extend(iter);
+        self.count = self.items.len();
+        self.build_heap();
+        self.debug_check_len();
+    }
+}
+
+impl<T> FromIterator<T> for Heap<T>
+where
+    T: Ord,
+{
+    /// Collect into a MinHeap; use `from_vec` for a max-heap or a custom
+    /// comparator
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_vec(iter.into_iter().collect(), |a, b| a < b)
+    }
+}
+
+impl<T> Default for Heap<T>
+where
+    T: Ord,
+{
+    /// An empty MinHeap, matching `new_min`
+    fn default() -> Self {
+        Self::new_min()
+    }
+}
+
+impl<T> Clone for Heap<T>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            count: self.count,
+            items: self.items.clone(),
+            comparator: self.comparator.clone(),
+        }
+    }
+
+    /// Reuse `self`'s buffer where it is large enough instead of reallocating
+    fn clone_from(&mut self, source: &Self) {
+        self.items.clone_from(&source.items);
+        self.count = source.count;
+        self.comparator = source.comparator.clone();
+        self.debug_check_len();
+    }
+}
+
+/// Heaps are equal when they hold the same multiset of elements, whatever
+/// their layout or comparator. Sorts copies of both, so O(n log n): meant
+/// for tests, not hot paths.
+impl<T> PartialEq for Heap<T>
+where
+    T: Ord + Clone,
+{
+    fn eq(&self, other: &Self) -> bool {
+        if self.count != other.count {
+            return false;
+        }
+        self.sorted_copy() == other.sorted_copy()
+    }
+}
+
+impl<T> Eq for Heap<T> where T: Ord + Clone {}
+
+/// Hashes the sorted multiset so it agrees with `PartialEq`, which makes it
+/// O(n log n) per hash
+impl<T> Hash for Heap<T>
+where
+    T: Ord + Clone + Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.sorted_copy().hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Heap<T>
+where
+    T: fmt::Debug,
+{
+    /// Show the raw array layout; printing in sorted order would need a drain
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Heap")
+            .field("count", &self.count)
+            .field("items", &&self.items[..self.count])
+            .finish()
+    }
+}
+
+/// Guard returned by `Heap::peek_mut`
+pub struct PeekMut<'a, T> {
+    heap: &'a mut Heap<T>,
+    // Set once the root has been handed out mutably
+    dirty: bool,
+}
+
+impl<T> Deref for PeekMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.heap.items[0]
+    }
+}
+
+impl<T> DerefMut for PeekMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.dirty = true;
+        &mut self.heap.items[0]
+    }
+}
+
+impl<T> Drop for PeekMut<'_, T> {
+    fn drop(&mut self) {
+        if self.dirty {
+            self.heap.bubble_down(0);
+        }
+        self.heap.debug_check_len();
+    }
+}
+
+/// Guard returned by `Heap::iter_mut`. Iterate it with `for x in &mut guard`
+/// or use it as a mutable slice. Yielded references borrow the guard rather
+/// than the heap, so the rebuild on drop cannot be skipped.
+pub struct IterMut<'a, T> {
+    heap: &'a mut Heap<T>,
+}
+
+impl<T> Deref for IterMut<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.heap.as_slice()
+    }
+}
+
+impl<T> DerefMut for IterMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.heap.items[..self.heap.count]
+    }
+}
+
+impl<'b, T> IntoIterator for &'b mut IterMut<'_, T> {
+    type Item = &'b mut T;
+    type IntoIter = std::slice::IterMut<'b, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.deref_mut().iter_mut()
+    }
+}
+
+impl<T> Drop for IterMut<'_, T> {
+    fn drop(&mut self) {
+        self.heap.build_heap();
+        self.heap.debug_check_len();
+    }
+}
+
+pub struct MinHeap;
+
+impl MinHeap {
+    pub fn new<T>() -> Heap<T>
+    where
+        T: Ord,
+    {
+        Heap::new(|a, b| a < b)
+    }
+}
+
+pub struct MaxHeap;
+
+impl MaxHeap {
+    pub fn new<T>() -> Heap<T>
+    where
+        T: Ord,
+    {
+        Heap::new(|a, b| a > b)
+    }
+}
+
+/// Stable reference to an element of an `IndexedHeap`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Handle {
+    id: usize,
+    // Bumped each time `id` is freed, so a stale handle to a recycled id is
+    // still caught
+    generation: u32,
+}
+
+/// A heap whose elements can be re-prioritised through the `Handle`
+/// returned by `add`
+pub struct IndexedHeap<T> {
+    count: usize,
+    items: Vec<T>,
+    // handles[slot] is the handle id of the element stored in items[slot]
+    handles: Vec<usize>,
+    // positions[id] is the slot holding that element, None once popped
+    positions: Vec<Option<usize>>,
+    generations: Vec<u32>,
+    // Ids freed by `pop`, reused by `add` so `positions` stays as long as the
+    // most elements ever held at once rather than the number of adds
+    free: Vec<usize>,
+    comparator: fn(&T, &T) -> bool,
+}
+
+impl<T> IndexedHeap<T> {
+    pub fn new(comparator: fn(&T, &T) -> bool) -> Self {
+        Self {
+            count: 0,
+            items: Vec::new(),
+            handles: Vec::new(),
+            positions: Vec::new(),
+            generations: Vec::new(),
+            free: Vec::new(),
+            comparator,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Insert a value, returning a handle that stays valid until it is popped
+    pub fn add(&mut self, value: T) -> Handle {
+        let id = self.free.pop().unwrap_or_else(|| {
+            self.positions.push(None);
+            self.generations.push(0);
+            self.positions.len() - 1
+        });
+        self.items.push(value);
+        self.handles.push(id);
+        self.positions[id] = Some(self.count);
+        self.count += 1;
+        self.bubble_up(self.count - 1);
+        Handle {
+            id,
+            generation: self.generations[id],
+        }
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.items[..self.count].first()
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.swap(0, self.count - 1);
+        let top = self.items.pop();
+        let id = self.handles.pop().unwrap();
+        self.positions[id] = None;
+        self.generations[id] = self.generations[id].wrapping_add(1);
+        self.free.push(id);
+        self.count -= 1;
+        self.bubble_down(0);
+        top
+    }
+
+    /// Replace the element behind `handle` and move it to its new place.
+    /// Panics if the element has already been popped.
+    pub fn change_priority(&mut self, handle: Handle, new_value: T) {
+        let idx = match self.positions[handle.id] {
+            Some(idx) if self.generations[handle.id] == handle.generation => idx,
+            _ => panic!("handle refers to a popped element"),
+        };
+        self.items[idx] = new_value;
+        self.bubble_up(idx);
+        let idx = self.positions[handle.id].unwrap();
+        self.bubble_down(idx);
+    }
+
+    // Every move of an element goes through here so positions stay in sync
+    fn swap(&mut self, a: usize, b: usize) {
+        self.items.swap(a, b);
+        self.handles.swap(a, b);
+        self.positions[self.handles[a]] = Some(a);
+        self.positions[self.handles[b]] = Some(b);
+    }
+
+    fn smallest_child_idx(&self, idx: usize) -> Option<usize> {
+        let left = 2 * idx + 1;
+        let right = 2 * idx + 2;
+
+        if left < self.count {
+            if right < self.count && !(self.comparator)(&self.items[left], &self.items[right]) {
+                Some(right)
+            } else {
+                Some(left)
+            }
+        } else {
+            None
+        }
+    }
+
+    fn bubble_down(&mut self, idx: usize) {
+        let mut parent_idx = idx;
+        while let Some(child_idx) = self.smallest_child_idx(parent_idx) {
+            if (self.comparator)(&self.items[parent_idx], &self.items[child_idx]) {
+                break;
+            }
+            self.swap(parent_idx, child_idx);
+            parent_idx = child_idx;
+        }
+    }
+
+    fn bubble_up(&mut self, idx: usize) {
+        let mut child_idx = idx;
+        while child_idx > 0 {
+            let parent_idx = (child_idx - 1) / 2;
+            if (self.comparator)(&self.items[child_idx], &self.items[parent_idx]) {
+                self.swap(child_idx, parent_idx);
+                child_idx = parent_idx;
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+impl<T> Heap<T>
+where
+    T: 'static,
+{
+    /// Create a heap that pops elements the comparator considers equal in
+    /// insertion order. `comparator` must be strict (`<`, not `<=`) for ties
+    /// to be detected.
+    pub fn new_stable(comparator: fn(&T, &T) -> bool) -> StableHeap<T> {
+        StableHeap {
+            heap: Heap::new_by(move |a: &(T, u64), b: &(T, u64)| {
+                comparator(&a.0, &b.0) || (!comparator(&b.0, &a.0) && a.1 < b.1)
+            }),
+            next_seq: 0,
+        }
+    }
+
+    /// Create a heap that pops the smallest `key(element)` first
+    pub fn new_by_key<K, F>(key: F) -> Self
+    where
+        K: Ord,
+        F: Fn(&T) -> K + Send + Sync + 'static,
+    {
+        Self::new_by(move |a, b| key(a) < key(b))
+    }
+
+    /// Create a heap that pops the largest `key(element)` first
+    pub fn new_by_key_max<K, F>(key: F) -> Self
+    where
+        K: Ord,
+        F: Fn(&T) -> K + Send + Sync + 'static,
+    {
+        Self::new_by(move |a, b| key(a) > key(b))
+    }
+}
+
+/// A heap that breaks ties by insertion order, built with `Heap::new_stable`
+pub struct StableHeap<T> {
+    heap: Heap<(T, u64)>,
+    next_seq: u64,
+}
+
+impl<T> StableHeap<T> {
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn add(&mut self, value: T) {
+        self.heap.add((value, self.next_seq));
+        self.next_seq += 1;
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.heap.peek().map(|(value, _)| value)
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.heap.pop().map(|(value, _)| value)
+    }
+}
+
+impl<T> Iterator for StableHeap<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.pop()
+    }
+}
+
+/// A heap with a fallible comparator, built with `Heap::new_try`
+pub struct TryHeap<T, E> {
+    items: Vec<T>,
+    comparator: fn(&T, &T) -> Result<bool, E>,
+}
+
+impl<T, E> TryHeap<T, E> {
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.items.first()
+    }
+
+    /// Add `value`, or return the comparator's error with the heap untouched
+    pub fn try_add(&mut self, value: T) -> Result<(), E> {
+        // Find where `value` settles before moving anything
+        let mut idx = self.items.len();
+        while idx > 0 {
+            let parent_idx = (idx - 1) / 2;
+            if !(self.comparator)(&value, &self.items[parent_idx])? {
+                break;
+            }
+            idx = parent_idx;
+        }
+        self.items.push(value);
+        let mut child_idx = self.items.len() - 1;
+        while child_idx > idx {
+            let parent_idx = (child_idx - 1) / 2;
+            self.items.swap(child_idx, parent_idx);
+            child_idx = parent_idx;
+        }
+        Ok(())
+    }
+
+    /// Pop the top, or return the comparator's error with the heap untouched
+    pub fn try_pop(&mut self) -> Result<Option<T>, E> {
+        let len = match self.items.len() {
+            0 => return Ok(None),
+            1 => return Ok(self.items.pop()),
+            len => len - 1,
+        };
+        // Trace the path the last element would sift down before moving
+        // anything; it stays at index `len` until then
+        let last = &self.items[len];
+        let mut path = vec![0];
+        let mut hole = 0;
+        loop {
+            let left = 2 * hole + 1;
+            let right = left + 1;
+            if left >= len {
+                break;
+            }
+            let child = if right < len && (self.comparator)(&self.items[right], &self.items[left])? {
+                right
+            } else {
+                left
+            };
+            if !(self.comparator)(&self.items[child], last)? {
+                break;
+            }
+            path.push(child);
+            hole = child;
+        }
+        for pair in path.windows(2) {
+            self.items.swap(pair[0], pair[1]);
+        }
+        // The old top has been walked down to `hole`
+        Ok(Some(self.items.swap_remove(hole)))
+    }
+}
+
+/// Keeps only the `k` largest elements seen, using O(k) memory
+pub struct BoundedHeap<T>
+where
+    T: Ord,
+{
+    k: usize,
+    // MinHeap whose root is the smallest survivor, i.e. the next to evict
+    heap: Heap<T>,
+}
+
+impl<T> BoundedHeap<T>
+where
+    T: Ord,
+{
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            heap: Heap::with_capacity(k, |a, b| a < b),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// Offer a value, keeping it only if it is among the `k` largest so far
+    pub fn add(&mut self, value: T) {
+        if self.heap.len() < self.k {
+            self.heap.add(value);
+        } else if self.heap.peek().is_some_and(|smallest| value > *smallest) {
+            self.heap.replace(value);
+        }
+    }
+
+    /// The smallest of the kept elements
+    pub fn peek(&self) -> Option<&T> {
+        self.heap.peek()
+    }
+}
+
+impl<T> Iterator for BoundedHeap<T>
+where
+    T: Ord,
+{
+    type Item = T;
+
+    /// Yields the kept elements smallest first
+    fn next(&mut self) -> Option<T> {
+        self.heap.pop()
+    }
+}
+
+/// A heap where every node has up to `D` children instead of two
+pub struct DaryHeap<T, const D: usize> {
+    count: usize,
+    items: Vec<T>,
+    comparator: fn(&T, &T) -> bool,
+}
+
+impl<T, const D: usize> DaryHeap<T, D> {
+    pub fn new(comparator: fn(&T, &T) -> bool) -> Self {
+        assert!(D > 0, "a d-ary heap needs at least one child per node");
+        Self {
+            count: 0,
+            items: Vec::new(),
+            comparator,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn add(&mut self, value: T) {
+        self.items.push(value);
+        self.count += 1;
+        self.bubble_up(self.count - 1);
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.items[..self.count].first()
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            None
+        } else {
+            let top = self.items.swap_remove(0);
+            self.count -= 1;
+            self.bubble_down(0);
+            Some(top)
+        }
+    }
+
+    fn parent_idx(&self, idx: usize) -> usize {
+        (idx - 1) / D
+    }
+
+    /// Index of the `k`-th child (0-based) of `idx`
+    fn child_idx(&self, idx: usize, k: usize) -> usize {
+        D * idx + k + 1
+    }
+
+    fn smallest_child_idx(&self, idx: usize) -> Option<usize> {
+        let mut best: Option<usize> = None;
+        for k in 0..D {
+            let child = self.child_idx(idx, k);
+            if child >= self.count {
+                break;
+            }
+            best = match best {
+                Some(b) if (self.comparator)(&self.items[b], &self.items[child]) => Some(b),
+                _ => Some(child),
+            };
+        }
+        best
+    }
+
+    fn bubble_down(&mut self, idx: usize) {
+        let mut parent_idx = idx;
+        while let Some(child_idx) = self.smallest_child_idx(parent_idx) {
+            if (self.comparator)(&self.items[parent_idx], &self.items[child_idx]) {
+                break;
+            }
+            self.items.swap(parent_idx, child_idx);
+            parent_idx = child_idx;
+        }
+    }
+
+    fn bubble_up(&mut self, idx: usize) {
+        let mut child_idx = idx;
+        while child_idx > 0 {
+            let parent_idx = self.parent_idx(child_idx);
+            if (self.comparator)(&self.items[child_idx], &self.items[parent_idx]) {
+                self.items.swap(child_idx, parent_idx);
+                child_idx = parent_idx;
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+impl<T, const D: usize> Iterator for DaryHeap<T, D> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.pop()
+    }
+}
+
+/// A heap shared between threads. Clones refer to the same underlying heap.
+pub struct SyncHeap<T> {
+    shared: Arc<Shared<T>>,
+}
+
+struct Shared<T> {
+    heap: Mutex<Heap<T>>,
+    // Signalled whenever an element is pushed
+    available: Condvar,
+    // Signalled whenever an element is popped, for pushers waiting on a
+    // full heap
+    space: Condvar,
+    capacity: Option<usize>,
+}
+
+impl<T> SyncHeap<T> {
+    pub fn new(comparator: fn(&T, &T) -> bool) -> Self {
+        Self::from_parts(Heap::new(comparator), None)
+    }
+
+    /// Create a heap holding at most `capacity` elements, turning it into a
+    /// bounded priority channel: `push` blocks while it is full
+    pub fn with_capacity(capacity: usize, comparator: fn(&T, &T) -> bool) -> Self {
+        assert!(capacity > 0, "capacity must be at least 1");
+        Self::from_parts(Heap::with_capacity(capacity, comparator), Some(capacity))
+    }
+
+    fn from_parts(heap: Heap<T>, capacity: Option<usize>) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                heap: Mutex::new(heap),
+                available: Condvar::new(),
+                space: Condvar::new(),
+                capacity,
+            }),
+        }
+    }
+
+    fn is_full(&self, heap: &Heap<T>) -> bool {
+        self.shared
+            .capacity
+            .is_some_and(|capacity| heap.len() >= capacity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.heap.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Push `value`, parking the thread while a bounded heap is full
+    pub fn push(&self, value: T) {
+        let mut heap = self.shared.heap.lock().unwrap();
+        while self.is_full(&heap) {
+            heap = self.shared.space.wait(heap).unwrap();
+        }
+        heap.add(value);
+        self.shared.available.notify_one();
+    }
+
+    /// Push `value` unless a bounded heap is full, in which case it is
+    /// handed back
+    pub fn try_push(&self, value: T) -> Result<(), T> {
+        let mut heap = self.shared.heap.lock().unwrap();
+        if self.is_full(&heap) {
+            return Err(value);
+        }
+        heap.add(value);
+        self.shared.available.notify_one();
+        Ok(())
+    }
+
+    /// Pop the top element, or `None` if the heap is currently empty
+    pub fn try_pop(&self) -> Option<T> {
+        let top = self.shared.heap.lock().unwrap().pop();
+        if top.is_some() {
+            self.shared.space.notify_one();
+        }
+        top
+    }
+
+    /// Pop the top element, parking the thread until one is available
+    pub fn pop(&self) -> T {
+        let mut heap = self.shared.heap.lock().unwrap();
+        loop {
+            if let Some(top) = heap.pop() {
+                self.shared.space.notify_one();
+                return top;
+            }
+            heap = self.shared.available.wait(heap).unwrap();
+        }
+    }
+
+    /// A copy of the top element
+    pub fn peek_cloned(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.shared.heap.lock().unwrap().peek().cloned()
+    }
+}
+
+impl<T> Clone for SyncHeap<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+/// A double-ended priority queue: even tree levels are ordered as a
+/// MinHeap and odd levels as a MaxHeap, so both extremes sit at the top
+pub struct MinMaxHeap<T>
+where
+    T: Ord,
+{
+    items: Vec<T>,
+}
+
+impl<T> MinMaxHeap<T>
+where
+    T: Ord,
+{
+    pub fn new() -> Self {
+        Self { items: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.items.push(value);
+        self.bubble_up(self.items.len() - 1);
+    }
+
+    pub fn peek_min(&self) -> Option<&T> {
+        self.items.first()
+    }
+
+    pub fn peek_max(&self) -> Option<&T> {
+        self.max_idx().map(|idx| &self.items[idx])
+    }
+
+    pub fn pop_min(&mut self) -> Option<T> {
+        self.remove_at(0)
+    }
+
+    pub fn pop_max(&mut self) -> Option<T> {
+        self.max_idx().and_then(|idx| self.remove_at(idx))
+    }
+
+    // The largest element is the root's larger child, or the root if alone
+    fn max_idx(&self) -> Option<usize> {
+        match self.items.len() {
+            0 => None,
+            1 => Some(0),
+            2 => Some(1),
+            _ => Some(if self.items[2] > self.items[1] { 2 } else { 1 }),
+        }
+    }
+
+    fn remove_at(&mut self, idx: usize) -> Option<T> {
+        if idx >= self.items.len() {
+            return None;
+        }
+        let removed = self.items.swap_remove(idx);
+        if idx < self.items.len() {
+            self.trickle_down(idx);
+        }
+        Some(removed)
+    }
+
+    fn is_min_level(idx: usize) -> bool {
+        (idx + 1).ilog2().is_multiple_of(2)
+    }
+
+    // Whether `a` belongs above `b` on a level of the given kind
+    fn ranks_above(min_level: bool, a: &T, b: &T) -> bool {
+        if min_level {
+            a < b
+        } else {
+            a > b
+        }
+    }
+
+    fn bubble_up(&mut self, idx: usize) {
+        if idx == 0 {
+            return;
+        }
+        let parent = (idx - 1) / 2;
+        let min_level = Self::is_min_level(idx);
+        // An element out of order with its parent belongs on the parent's
+        // kind of level instead
+        if Self::ranks_above(!min_level, &self.items[idx], &self.items[parent]) {
+            self.items.swap(idx, parent);
+            self.bubble_up_grandparents(parent, !min_level);
+        } else {
+            self.bubble_up_grandparents(idx, min_level);
+        }
+    }
+
+    fn bubble_up_grandparents(&mut self, mut idx: usize, min_level: bool) {
+        while idx > 2 {
+            let grandparent = ((idx - 1) / 2 - 1) / 2;
+            if !Self::ranks_above(min_level, &self.items[idx], &self.items[grandparent]) {
+                break;
+            }
+            self.items.swap(idx, grandparent);
+            idx = grandparent;
+        }
+    }
+
+    fn trickle_down(&mut self, mut idx: usize) {
+        let min_level = Self::is_min_level(idx);
+        let len = self.items.len();
+        loop {
+            let first_child = 2 * idx + 1;
+            if first_child >= len {
+                break;
+            }
+            // The best of the children and grandchildren
+            let first_grandchild = 4 * idx + 3;
+            let mut best = first_child;
+            let candidates = (first_child + 1..first_child + 2)
+                .chain(first_grandchild..first_grandchild + 4)
+                .filter(|&i| i < len);
+            for i in candidates {
+                if Self::ranks_above(min_level, &self.items[i], &self.items[best]) {
+                    best = i;
+                }
+            }
+            if !Self::ranks_above(min_level, &self.items[best], &self.items[idx]) {
+                break;
+            }
+            self.items.swap(best, idx);
+            if best < first_grandchild {
+                break;
+            }
+            // The displaced element may now be out of order with the
+            // grandchild's parent, which sits on the opposite kind of level
+            let parent = (best - 1) / 2;
+            if Self::ranks_above(min_level, &self.items[parent], &self.items[best]) {
+                self.items.swap(best, parent);
+            }
+            idx = best;
+        }
+    }
+}
+
+impl<T> Default for MinMaxHeap<T>
+where
+    T: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sort `slice` ascending in place without allocating
+pub fn heapsort<T: Ord>(slice: &mut [T]) {
+    // Build a max-heap so the largest element can be swapped to the end
+    let higher = |a: &T, b: &T| a > b;
+    heapify(slice, higher);
+    for end in (1..slice.len()).rev() {
+        slice.swap(0, end);
+        sift_down(&mut slice[..end], 0, &higher);
+    }
+}
+
+/// Arrange `items` in place as a heap array where no child ranks higher than
+/// its parent under `comparator`, in O(n)
+pub fn heapify<T, F: Fn(&T, &T) -> bool>(items: &mut [T], comparator: F) {
+    for idx in (0..items.len() / 2).rev() {
+        sift_down(items, idx, &comparator);
+    }
+}
+
+// `a < b`, with values unordered even against themselves ranked greatest
+fn partial_less<T: PartialOrd>(a: &T, b: &T) -> bool {
+    let unordered = |x: &T| x.partial_cmp(x).is_none();
+    match (unordered(a), unordered(b)) {
+        (false, false) => a < b,
+        (false, true) => true,
+        (true, _) => false,
+    }
+}
+
+// Move items[idx] down until neither child ranks higher under `higher`
+fn sift_down<T, F: Fn(&T, &T) -> bool>(items: &mut [T], idx: usize, higher: &F) {
+    let mut parent_idx = idx;
+    loop {
+        let left = 2 * parent_idx + 1;
+        let right = left + 1;
+        if left >= items.len() {
+            break;
+        }
+        let child_idx = if right < items.len() && higher(&items[right], &items[left]) {
+            right
+        } else {
+            left
+        };
+        if !higher(&items[child_idx], &items[parent_idx]) {
+            break;
+        }
+        items.swap(parent_idx, child_idx);
+        parent_idx = child_idx;
+    }
+}
+
+// Move items[idx] up until its parent ranks at least as high under `higher`
+fn sift_up<T, F: Fn(&T, &T) -> bool>(items: &mut [T], mut idx: usize, higher: &F) {
+    while idx > 0 {
+        let parent_idx = (idx - 1) / 2;
+        if !higher(&items[idx], &items[parent_idx]) {
+            break;
+        }
+        items.swap(idx, parent_idx);
+        idx = parent_idx;
+    }
+}
+
+/// Merge already-sorted iterators into one ascending stream
+pub fn merge_sorted<I, T>(mut iters: Vec<I>) -> impl Iterator<Item = T>
+where
+    I: Iterator<Item = T>,
+    T: Ord,
+{
+    // Current head of every source, tagged with the index it came from
+    let mut heads = Heap::with_capacity(iters.len(), |a: &(T, usize), b: &(T, usize)| a < b);
+    for (source, iter) in iters.iter_mut().enumerate() {
+        if let Some(value) = iter.next() {
+            heads.add((value, source));
+        }
+    }
+    std::iter::from_fn(move || {
+        let (value, source) = heads.pop()?;
+        if let Some(next) = iters[source].next() {
+            heads.add((next, source));
+        }
+        Some(value)
+    })
+}
+
+/// Maintains the median of a stream using a MaxHeap of the lower half and a
+/// MinHeap of the upper half
+pub struct RunningMedian<T>
+where
+    T: Ord + Clone,
+{
+    // Holds the extra element when the count is odd
+    low: Heap<T>,
+    high: Heap<T>,
+}
+
+impl<T> RunningMedian<T>
+where
+    T: Ord + Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            low: Heap::new_max(),
+            high: Heap::new_min(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.low.len() + self.high.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn add(&mut self, value: T) {
+        match self.low.peek() {
+            Some(top) if value > *top => self.high.add(value),
+            _ => self.low.add(value),
+        }
+        if self.low.len() > self.high.len() + 1 {
+            let moved = self.low.pop().unwrap();
+            self.high.add(moved);
+        } else if self.high.len() > self.low.len() {
+            let moved = self.high.pop().unwrap();
+            self.low.add(moved);
+        }
+    }
+
+    /// The median so far, averaging the two middle values for an even count
+    pub fn median(&self) -> Option<f64>
+    where
+        T: Into<f64>,
+    {
+        let low: f64 = self.low.peek()?.clone().into();
+        if self.low.len() > self.high.len() {
+            Some(low)
+        } else {
+            let high: f64 = self.high.peek()?.clone().into();
+            Some((low + high) / 2.0)
+        }
+    }
+}
+
+impl<T> Default for RunningMedian<T>
+where
+    T: Ord + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Answers "what is the k-th largest value so far" over a stream
+pub struct KthLargest<T>
+where
+    T: Ord,
+{
+    k: usize,
+    // MinHeap of the k largest values; its root is the answer
+    heap: Heap<T>,
+}
+
+impl<T> KthLargest<T>
+where
+    T: Ord,
+{
+    pub fn new(k: usize, initial: Vec<T>) -> Self {
+        assert!(k > 0, "k must be at least 1");
+        let mut heap = Heap::from_vec(initial, |a, b| a < b);
+        while heap.len() > k {
+            heap.pop();
+        }
+        Self { k, heap }
+    }
+
+    /// Insert `value` and return the current k-th largest. With fewer than
+    /// `k` values seen this is the smallest of them.
+    pub fn add(&mut self, value: T) -> &T {
+        self.heap.add(value);
+        if self.heap.len() > self.k {
+            self.heap.pop();
+        }
+        self.heap.peek().unwrap()
+    }
+}
+
+/// The `n` largest items, largest first, using O(n) memory
+pub fn nlargest<T: Ord>(n: usize, iter: impl IntoIterator<Item = T>) -> Vec<T> {
+    // MinHeap of survivors: push_pop evicts the smallest once full
+    let mut kept = Heap::with_capacity(n, |a: &T, b: &T| a < b);
+    for value in iter {
+        if kept.len() < n {
+            kept.add(value);
+        } else {
+            kept.push_pop(value);
+        }
+    }
+    let mut largest = kept.into_sorted_vec();
+    largest.reverse();
+    largest
+}
+
+/// The `n` smallest items, smallest first, using O(n) memory
+pub fn nsmallest<T: Ord>(n: usize, iter: impl IntoIterator<Item = T>) -> Vec<T> {
+    // MaxHeap of survivors: push_pop evicts the largest once full
+    let mut kept = Heap::with_capacity(n, |a: &T, b: &T| a > b);
+    for value in iter {
+        if kept.len() < n {
+            kept.add(value);
+        } else {
+            kept.push_pop(value);
+        }
+    }
+    let mut smallest = kept.into_sorted_vec();
+    smallest.reverse();
+    smallest
+}
+
+/// Shortest distance from `source` to every node of a graph given as
+/// adjacency lists of `(neighbour, weight)`, or `None` if unreachable
+pub fn dijkstra(adj: &[Vec<(usize, u64)>], source: usize) -> Vec<Option<u64>> {
+    let mut dist = vec![None; adj.len()];
+    let mut frontier = MinHeap::new();
+    dist[source] = Some(0);
+    frontier.add((0, source));
+    while let Some((d, node)) = frontier.pop() {
+        // A shorter path to `node` was found after this entry was queued
+        if dist[node].is_some_and(|best| d > best) {
+            continue;
+        }
+        for &(next, weight) in &adj[node] {
+            let candidate = d + weight;
+            if dist[next].is_none_or(|best| candidate < best) {
+                dist[next] = Some(candidate);
+                frontier.add((candidate, next));
+            }
+        }
+    }
+    dist
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_empty_heap() {
+        let mut heap = MaxHeap::new::<i32>();
+        assert_eq!(heap.next(), None);
+    }
+
+    #[test]
+    fn test_min_heap() {
+        let mut heap = MinHeap::new();
+        heap.add(4);
+        heap.add(2);
+        heap.add(9);
+        heap.add(11);
+        assert_eq!(heap.len(), 4);
+        assert_eq!(heap.next(), Some(2));
+        assert_eq!(heap.next(), Some(4));
+        assert_eq!(heap.next(), Some(9));
+        heap.add(1);
+        assert_eq!(heap.next(), Some(1));
+    }
+
+    #[test]
+    fn test_max_heap() {
+        let mut heap = MaxHeap::new();
+        heap.add(4);
+        heap.add(2);
+        heap.add(9);
+        heap.add(11);
+        assert_eq!(heap.len(), 4);
+        assert_eq!(heap.next(), Some(11));
+        assert_eq!(heap.next(), Some(9));
+        assert_eq!(heap.next(), Some(4));
+        heap.add(1);
+        assert_eq!(heap.next(), Some(2));
+    }
+
+    #[test]
+    fn test_peek_empty() {
+        let heap = MinHeap::new::<i32>();
+        assert_eq!(heap.peek(), None);
+    }
+
+    #[test]
+    fn test_peek_after_add() {
+        let mut heap = MinHeap::new();
+        heap.add(4);
+        assert_eq!(heap.peek(), Some(&4));
+        heap.add(2);
+        heap.add(9);
+        assert_eq!(heap.peek(), Some(&2));
+        assert_eq!(heap.len(), 3);
+        assert_eq!(heap.next(), Some(2));
+        assert_eq!(heap.peek(), Some(&4));
+
+        let mut heap = MaxHeap::new();
+        heap.add(4);
+        heap.add(11);
+        heap.add(9);
+        assert_eq!(heap.peek(), Some(&11));
+        assert_eq!(heap.len(), 3);
+    }
+
+    #[test]
+    fn test_into_sorted_vec() {
+        let mut heap = MinHeap::new();
+        for value in [4, 2, 9, 2, 11] {
+            heap.add(value);
+        }
+        assert_eq!(heap.into_sorted_vec(), vec![2, 2, 4, 9, 11]);
+
+        let mut heap = MaxHeap::new();
+        for value in [4, 2, 9, 2, 11] {
+            heap.add(value);
+        }
+        assert_eq!(heap.into_sorted_vec(), vec![11, 9, 4, 2, 2]);
+    }
+
+    #[test]
+    fn test_into_sorted_vec_empty() {
+        let heap = MinHeap::new::<i32>();
+        assert!(heap.into_sorted_vec().is_empty());
+    }
+
+    #[test]
+    fn test_from_vec() {
+        let items: Vec<i32> = (0..1000).map(|i| (i * 7919) % 1000).collect();
+        let mut heap = Heap::from_vec(items, |a, b| a < b);
+        assert_eq!(heap.len(), 1000);
+        for expected in 0..1000 {
+            assert_eq!(heap.next(), Some(expected));
+        }
+        assert_eq!(heap.next(), None);
+    }
+
+    #[test]
+    fn test_from_vec_empty() {
+        let mut heap = Heap::<i32>::from_vec(Vec::new(), |a, b| a < b);
+        assert!(heap.is_empty());
+        assert_eq!(heap.peek(), None);
+        heap.add(3);
+        heap.add(1);
+        assert_eq!(heap.next(), Some(1));
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        let mut heap = Heap::with_capacity(16, |a: &i32, b: &i32| a < b);
+        assert!(heap.is_empty());
+        assert!(heap.capacity() >= 16);
+        heap.add(5);
+        heap.add(3);
+        assert_eq!(heap.next(), Some(3));
+        assert_eq!(heap.next(), Some(5));
+    }
+
+    #[test]
+    fn test_clear_and_refill() {
+        let mut heap = MinHeap::new();
+        for value in [7, 3, 5] {
+            heap.add(value);
+        }
+        let capacity = heap.capacity();
+        heap.clear();
+        assert!(heap.is_empty());
+        assert_eq!(heap.capacity(), capacity);
+        assert_eq!(heap.next(), None);
+
+        for value in [8, 1, 6] {
+            heap.add(value);
+        }
+        assert_eq!(heap.next(), Some(1));
+        assert_eq!(heap.next(), Some(6));
+        assert_eq!(heap.next(), Some(8));
+        assert_eq!(heap.next(), None);
+    }
+
+    #[test]
+    fn test_drain_full() {
+        let mut heap = MinHeap::new();
+        for value in [5, 1, 4, 2, 3] {
+            heap.add(value);
+        }
+        let drained: Vec<i32> = heap.drain().collect();
+        assert_eq!(drained, vec![1, 2, 3, 4, 5]);
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    fn test_drain_partial_then_add() {
+        let mut heap = MinHeap::new();
+        for value in [5, 1, 4, 2, 3] {
+            heap.add(value);
+        }
+        let prefix: Vec<i32> = heap.drain().take(2).collect();
+        assert_eq!(prefix, vec![1, 2]);
+        assert_eq!(heap.len(), 3);
+        heap.add(0);
+        assert_eq!(heap.into_sorted_vec(), vec![0, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_drain_empty() {
+        let mut heap = MinHeap::new::<i32>();
+        assert_eq!(heap.drain().next(), None);
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut heap = MinHeap::new();
+        for value in [10, 3, 7] {
+            heap.add(value);
+        }
+        heap.extend((0..6).rev());
+        assert_eq!(heap.len(), 9);
+        assert_eq!(heap.into_sorted_vec(), vec![0, 1, 2, 3, 3, 4, 5, 7, 10]);
+    }
+
+    #[test]
+    fn test_collect() {
+        let mut heap: Heap<i32> = vec![5, 1, 3].into_iter().collect();
+        assert_eq!(heap.len(), 3);
+        assert_eq!(heap.next(), Some(1));
+        assert_eq!(heap.next(), Some(3));
+        assert_eq!(heap.next(), Some(5));
+    }
+
+    #[test]
+    fn test_append() {
+        let mut a = MinHeap::new();
+        let mut b = MinHeap::new();
+        for value in [8, 2, 6] {
+            a.add(value);
+        }
+        for value in [7, 1, 5, 3] {
+            b.add(value);
+        }
+        a.append(&mut b);
+        assert!(b.is_empty());
+        assert_eq!(b.next(), None);
+        assert_eq!(a.len(), 7);
+        assert_eq!(a.into_sorted_vec(), vec![1, 2, 3, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_pop_matches_next() {
+        let values = [6, 3, 9, 3, 1, 12, 0];
+        let mut popped = MinHeap::new();
+        let mut iterated = MinHeap::new();
+        for value in values {
+            popped.add(value);
+            iterated.add(value);
+        }
+        let mut by_pop = Vec::new();
+        while let Some(value) = popped.pop() {
+            by_pop.push(value);
+        }
+        let by_next: Vec<i32> = iterated.collect();
+        assert_eq!(by_pop, by_next);
+        assert_eq!(popped.pop(), None);
+    }
+
+    #[test]
+    fn test_remove_leaf() {
+        let mut heap = MinHeap::new();
+        for value in [1, 2, 3, 4, 5, 6, 7] {
+            heap.add(value);
+        }
+        assert!(heap.remove(&7));
+        assert_eq!(heap.len(), 6);
+        assert_eq!(heap.into_sorted_vec(), vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_remove_interior() {
+        let mut heap = MinHeap::new();
+        for value in [1, 10, 2, 11, 12, 3, 4] {
+            heap.add(value);
+        }
+        // 11 sits at index 3 and is replaced by 4, which must bubble up past 10
+        assert!(heap.remove(&11));
+        assert_eq!(heap.items[1], 4);
+        assert!(heap.remove(&1));
+        assert_eq!(heap.into_sorted_vec(), vec![2, 3, 4, 10, 12]);
+    }
+
+    #[test]
+    fn test_remove_missing() {
+        let mut heap = MinHeap::new();
+        for value in [3, 1, 2] {
+            heap.add(value);
+        }
+        assert!(!heap.remove(&5));
+        assert_eq!(heap.len(), 3);
+        assert!(!MinHeap::new::<i32>().remove(&1));
+    }
+
+    #[test]
+    fn test_change_priority_surfaces_to_root() {
+        let mut heap = IndexedHeap::new(|a: &i32, b: &i32| a < b);
+        let mut handles = Vec::new();
+        for value in [10, 20, 30, 40, 50, 60, 70] {
+            handles.push(heap.add(value));
+        }
+        // 50 lives in the middle of the tree; make it the smallest
+        heap.change_priority(handles[4], 5);
+        assert_eq!(heap.peek(), Some(&5));
+
+        // Handles of elements moved by the sift are still valid
+        heap.change_priority(handles[0], 65);
+        heap.change_priority(handles[6], 1);
+        let drained: Vec<i32> = std::iter::from_fn(|| heap.pop()).collect();
+        assert_eq!(drained, vec![1, 5, 20, 30, 40, 60, 65]);
+    }
+
+    #[test]
+    fn test_change_priority_increase_key() {
+        let mut heap = IndexedHeap::new(|a: &i32, b: &i32| a < b);
+        let root = heap.add(1);
+        heap.add(2);
+        heap.add(3);
+        heap.change_priority(root, 9);
+        assert_eq!(heap.pop(), Some(2));
+        heap.change_priority(root, 0);
+        assert_eq!(heap.pop(), Some(0));
+        assert_eq!(heap.pop(), Some(3));
+        assert_eq!(heap.pop(), None);
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "popped")]
+    fn test_change_priority_popped_handle() {
+        let mut heap = IndexedHeap::new(|a: &i32, b: &i32| a < b);
+        let handle = heap.add(1);
+        heap.pop();
+        // Reuses the freed id, but not the stale handle's generation
+        heap.add(3);
+        heap.change_priority(handle, 2);
+    }
+
+    #[test]
+    fn test_indexed_heap_recycles_handles() {
+        let mut heap = IndexedHeap::new(|a: &u32, b: &u32| a < b);
+        for i in 0..4 {
+            heap.add(i);
+        }
+        for i in 4..1000 {
+            let handle = heap.add(i);
+            heap.change_priority(handle, i + 1);
+            heap.pop();
+        }
+        assert_eq!(heap.len(), 4);
+        assert!(heap.positions.len() <= 5);
+    }
+
+    #[test]
+    fn test_contains() {
+        let mut heap = MinHeap::new();
+        for value in [8, 3, 5] {
+            heap.add(value);
+        }
+        assert!(heap.contains(&3));
+        assert!(heap.contains(&8));
+        assert!(!heap.contains(&4));
+        heap.pop();
+        assert!(!heap.contains(&3));
+        assert!(!MinHeap::new::<i32>().contains(&0));
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut heap = MaxHeap::new();
+        for value in [4, 9, 1, 9, 6] {
+            heap.add(value);
+        }
+        let mut seen: Vec<i32> = heap.iter().copied().collect();
+        seen.sort();
+        assert_eq!(seen, vec![1, 4, 6, 9, 9]);
+        assert_eq!(heap.len(), 5);
+        assert_eq!(heap.peek(), Some(&9));
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut heap = MinHeap::new();
+        heap.add(4);
+        heap.add(1);
+        heap.add(9);
+        assert_eq!(format!("{:?}", heap), "Heap { count: 3, items: [1, 4, 9] }");
+    }
+
+    #[test]
+    fn test_clone_is_independent() {
+        let mut heap = MinHeap::new();
+        for value in [5, 2, 8, 1] {
+            heap.add(value);
+        }
+        let mut snapshot = heap.clone();
+        assert_eq!(snapshot.drain().count(), 4);
+        assert!(snapshot.is_empty());
+        assert_eq!(heap.len(), 4);
+        assert_eq!(heap.into_sorted_vec(), vec![1, 2, 5, 8]);
+    }
+
+    #[test]
+    fn test_new_by_captured_origin() {
+        let origin = (10, 10);
+        let distance = move |p: &(i32, i32)| (p.0 - origin.0).pow(2) + (p.1 - origin.1).pow(2);
+        let mut heap = Heap::new_by(move |a, b| distance(a) < distance(b));
+        for point in [(0, 0), (9, 12), (20, 20), (11, 10), (3, 4)] {
+            heap.add(point);
+        }
+        assert_eq!(heap.pop(), Some((11, 10)));
+        assert_eq!(heap.pop(), Some((9, 12)));
+        assert_eq!(heap.pop(), Some((3, 4)));
+
+        let cloned = heap.clone();
+        assert_eq!(cloned.into_sorted_vec(), heap.into_sorted_vec());
+    }
+
+    #[test]
+    fn test_stable_ties_drain_fifo() {
+        let mut heap = Heap::new_stable(|a: &(u32, &str), b: &(u32, &str)| a.0 < b.0);
+        heap.add((2, "first two"));
+        heap.add((1, "first one"));
+        heap.add((2, "second two"));
+        heap.add((1, "second one"));
+        heap.add((2, "third two"));
+        heap.add((1, "third one"));
+        assert_eq!(heap.len(), 6);
+        assert_eq!(heap.peek(), Some(&(1, "first one")));
+        let payloads: Vec<&str> = heap.map(|(_, payload)| payload).collect();
+        assert_eq!(
+            payloads,
+            vec!["first one", "second one", "third one", "first two", "second two", "third two"]
+        );
+    }
+
+    #[test]
+    fn test_bounded_heap_keeps_top_k() {
+        let mut top = BoundedHeap::new(5);
+        for i in 0..10000u32 {
+            top.add((i * 7919) % 10000);
+            assert!(top.len() <= 5);
+        }
+        assert_eq!(top.peek(), Some(&9995));
+        assert_eq!(top.collect::<Vec<_>>(), vec![9995, 9996, 9997, 9998, 9999]);
+    }
+
+    #[test]
+    fn test_bounded_heap_zero_capacity() {
+        let mut top = BoundedHeap::new(0);
+        top.add(1);
+        assert!(top.is_empty());
+    }
+
+    fn check_dary_drains_sorted<const D: usize>() {
+        let mut heap = DaryHeap::<i32, D>::new(|a, b| a < b);
+        for i in 0..200 {
+            heap.add((i * 37) % 200);
+        }
+        heap.add(50);
+        assert_eq!(heap.len(), 201);
+        assert_eq!(heap.peek(), Some(&0));
+        let drained: Vec<i32> = heap.collect();
+        let mut expected: Vec<i32> = (0..200).collect();
+        expected.push(50);
+        expected.sort();
+        assert_eq!(drained, expected);
+    }
+
+    #[test]
+    fn test_dary_heap_binary() {
+        check_dary_drains_sorted::<2>();
+    }
+
+    #[test]
+    fn test_dary_heap_quaternary() {
+        check_dary_drains_sorted::<4>();
+    }
+
+    #[test]
+    fn test_dary_heap_octonary() {
+        check_dary_drains_sorted::<8>();
+    }
+
+    #[test]
+    fn test_dary_heap_max() {
+        let mut heap = DaryHeap::<i32, 4>::new(|a, b| a > b);
+        for value in [3, 9, 1, 7, 5] {
+            heap.add(value);
+        }
+        assert_eq!(heap.collect::<Vec<_>>(), vec![9, 7, 5, 3, 1]);
+    }
+
+    #[test]
+    fn test_sync_heap_producers_consumer() {
+        use std::thread;
+
+        let heap = SyncHeap::new(|a: &u32, b: &u32| a < b);
+        let producers: Vec<_> = (0..4)
+            .map(|p| {
+                let heap = heap.clone();
+                thread::spawn(move || {
+                    for i in 0..25 {
+                        heap.push(i * 4 + p);
+                    }
+                })
+            })
+            .collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        assert_eq!(heap.len(), 100);
+        assert_eq!(heap.peek_cloned(), Some(0));
+
+        let consumer = {
+            let heap = heap.clone();
+            thread::spawn(move || (0..100).map(|_| heap.pop()).collect::<Vec<_>>())
+        };
+        assert_eq!(consumer.join().unwrap(), (0..100).collect::<Vec<_>>());
+        assert!(heap.is_empty());
+        assert_eq!(heap.try_pop(), None);
+    }
+
+    #[test]
+    fn test_sync_heap_pop_blocks_until_push() {
+        use std::thread;
+        use std::time::Duration;
+
+        let heap = SyncHeap::new(|a: &u32, b: &u32| a < b);
+        let consumer = {
+            let heap = heap.clone();
+            thread::spawn(move || heap.pop())
+        };
+        thread::sleep(Duration::from_millis(50));
+        heap.push(7);
+        assert_eq!(consumer.join().unwrap(), 7);
+    }
+
+    #[test]
+    fn test_bounded_sync_heap_blocks_producers() {
+        use std::thread;
+        use std::time::Duration;
+
+        let heap = SyncHeap::with_capacity(5, |a: &u32, b: &u32| a < b);
+        let producers: Vec<_> = (0..3)
+            .map(|p| {
+                let heap = heap.clone();
+                thread::spawn(move || {
+                    for i in 0..20 {
+                        heap.push(i * 3 + p);
+                        assert!(heap.len() <= 5);
+                    }
+                })
+            })
+            .collect();
+        let mut received = Vec::new();
+        while received.len() < 60 {
+            thread::sleep(Duration::from_millis(1));
+            assert!(heap.len() <= 5);
+            received.push(heap.pop());
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        received.sort();
+        assert_eq!(received, (0..60).collect::<Vec<_>>());
+
+        for i in 0..5 {
+            heap.try_push(i).unwrap();
+        }
+        assert_eq!(heap.try_push(99), Err(99));
+        assert_eq!(heap.try_pop(), Some(0));
+        assert_eq!(heap.try_push(99), Ok(()));
+        for expected in [1, 2, 3, 4, 99] {
+            assert_eq!(heap.try_pop(), Some(expected));
+        }
+        assert_eq!(heap.try_pop(), None);
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut heap = MinHeap::new();
+        for value in [1, 4, 9] {
+            heap.add(value);
+        }
+        assert_eq!(heap.replace(6), Some(1));
+        assert_eq!(heap.len(), 3);
+        assert_eq!(heap.into_sorted_vec(), vec![4, 6, 9]);
+    }
+
+    #[test]
+    fn test_replace_empty() {
+        let mut heap = MinHeap::new();
+        assert_eq!(heap.replace(3), None);
+        assert_eq!(heap.pop(), Some(3));
+    }
+
+    #[test]
+    fn test_push_pop_short_circuit() {
+        let mut heap = MinHeap::new();
+        for value in [3, 5, 8] {
+            heap.add(value);
+        }
+        assert_eq!(heap.push_pop(1), 1);
+        assert_eq!(heap.len(), 3);
+        assert_eq!(heap.peek(), Some(&3));
+        assert_eq!(MinHeap::new().push_pop(4), 4);
+    }
+
+    #[test]
+    fn test_push_pop_replaces_root() {
+        let mut heap = MinHeap::new();
+        for value in [3, 5, 8] {
+            heap.add(value);
+        }
+        assert_eq!(heap.push_pop(6), 3);
+        assert_eq!(heap.len(), 3);
+        assert_eq!(heap.into_sorted_vec(), vec![5, 6, 8]);
+    }
+
+    #[test]
+    fn test_retain_odd() {
+        let mut heap: Heap<i32> = (0..20).rev().collect();
+        heap.retain(|x| x % 2 == 1);
+        assert_eq!(heap.len(), 10);
+        assert_eq!(heap.into_sorted_vec(), (0..10).map(|x| 2 * x + 1).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_retain_none_and_all() {
+        let mut heap: Heap<i32> = vec![4, 2, 7].into_iter().collect();
+        heap.retain(|_| true);
+        assert_eq!(heap.clone().into_sorted_vec(), vec![2, 4, 7]);
+        heap.retain(|_| false);
+        assert!(heap.is_empty());
+        heap.add(1);
+        assert_eq!(heap.pop(), Some(1));
+    }
+
+    // Deterministic pseudo-random values so failures are reproducible
+    fn pseudo_random(len: usize, seed: u64) -> Vec<i64> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                ((state >> 33) % 1000) as i64
+            })
+            .collect()
+    }
+
+    fn check_heapsort(mut values: Vec<i64>) {
+        let mut expected = values.clone();
+        expected.sort();
+        heapsort(&mut values);
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn test_heapsort_random() {
+        for seed in 0..20 {
+            check_heapsort(pseudo_random(seed as usize * 13, seed));
+        }
+    }
+
+    #[test]
+    fn test_heapsort_sorted_and_reversed() {
+        check_heapsort((0..100).collect());
+        check_heapsort((0..100).rev().collect());
+        check_heapsort(Vec::new());
+        check_heapsort(vec![42]);
+    }
+
+    #[test]
+    fn test_heapsort_duplicates() {
+        check_heapsort(vec![3, 1, 3, 3, 0, 1, 2, 2, 3, 0]);
+        check_heapsort(vec![5; 17]);
+    }
+
+    #[test]
+    fn test_new_by_ord() {
+        let mut heap = Heap::new_by_ord(|a: &i32, b: &i32| a.cmp(b));
+        for value in [5, 3, 8, 3, 1] {
+            heap.add(value);
+        }
+        assert_eq!(heap.into_sorted_vec(), vec![1, 3, 3, 5, 8]);
+
+        let mut heap = Heap::new_by_ord(|a: &i32, b: &i32| b.cmp(a));
+        for value in [5, 3, 8, 3, 1] {
+            heap.add(value);
+        }
+        assert_eq!(heap.into_sorted_vec(), vec![8, 5, 3, 3, 1]);
+    }
+
+    #[test]
+    fn test_peek_mut_resifts_on_drop() {
+        let mut heap = MinHeap::new();
+        for value in [1, 4, 6, 9] {
+            heap.add(value);
+        }
+        {
+            let mut top = heap.peek_mut().unwrap();
+            assert_eq!(*top, 1);
+            *top = 7;
+        }
+        assert_eq!(heap.peek(), Some(&4));
+        assert_eq!(heap.into_sorted_vec(), vec![4, 6, 7, 9]);
+    }
+
+    #[test]
+    fn test_peek_mut_read_only_and_empty() {
+        let mut heap = MinHeap::new::<i32>();
+        assert!(heap.peek_mut().is_none());
+        heap.add(2);
+        heap.add(3);
+        assert_eq!(*heap.peek_mut().unwrap(), 2);
+        assert_eq!(heap.pop(), Some(2));
+    }
+
+    #[test]
+    fn test_from_sorted_vec() {
+        let mut heap = Heap::from_sorted_vec(vec![1, 2, 2, 5, 8, 13], |a, b| a < b);
+        assert_eq!(heap.len(), 6);
+        heap.add(3);
+        assert_eq!(heap.into_sorted_vec(), vec![1, 2, 2, 3, 5, 8, 13]);
+
+        let heap = Heap::from_sorted_vec(vec![9, 4, 1], |a, b| a > b);
+        assert_eq!(heap.into_sorted_vec(), vec![9, 4, 1]);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "heap property")]
+    fn test_from_sorted_vec_rejects_unsorted() {
+        Heap::from_sorted_vec(vec![3, 1, 2], |a, b| a < b);
+    }
+
+    #[test]
+    fn test_from_sorted_vec_accepts_any_valid_heap() {
+        // Not sorted, but already a valid heap, so the debug check allows it
+        let heap = Heap::from_sorted_vec(vec![1, 3, 2], |a, b| a < b);
+        assert_eq!(heap.into_sorted_vec(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_split_off() {
+        let mut heap: Heap<i32> = (0..10).rev().collect();
+        let mut front = heap.split_off(3);
+        assert_eq!(front.len(), 3);
+        assert_eq!(heap.len(), 7);
+        front.add(-1);
+        assert_eq!(front.into_sorted_vec(), vec![-1, 0, 1, 2]);
+        assert_eq!(heap.into_sorted_vec(), (3..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_split_off_everything() {
+        let mut heap: Heap<i32> = vec![2, 1, 3].into_iter().collect();
+        let all = heap.split_off(10);
+        assert!(heap.is_empty());
+        assert_eq!(all.into_sorted_vec(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_nth_smallest() {
+        let heap: Heap<i32> = vec![50, 10, 40, 20, 30].into_iter().collect();
+        assert_eq!(heap.nth_smallest(0), Some(10));
+        assert_eq!(heap.nth_smallest(2), Some(30));
+        assert_eq!(heap.nth_smallest(4), Some(50));
+        assert_eq!(heap.nth_smallest(5), None);
+        assert_eq!(heap.len(), 5);
+        assert_eq!(heap.peek(), Some(&10));
+    }
+
+    #[test]
+    fn test_merge_sorted() {
+        let merged: Vec<i32> = merge_sorted(vec![
+            vec![1, 4, 7, 10].into_iter(),
+            vec![2, 5, 8].into_iter(),
+            vec![0, 3, 6, 9, 12, 15].into_iter(),
+        ])
+        .collect();
+        assert_eq!(merged, vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 12, 15]);
+    }
+
+    #[test]
+    fn test_merge_sorted_empty_inputs() {
+        let merged: Vec<i32> = merge_sorted(vec![
+            Vec::new().into_iter(),
+            vec![1, 1, 2].into_iter(),
+            Vec::new().into_iter(),
+        ])
+        .collect();
+        assert_eq!(merged, vec![1, 1, 2]);
+
+        let none: Vec<std::vec::IntoIter<i32>> = Vec::new();
+        assert_eq!(merge_sorted(none).count(), 0);
+    }
+
+    #[test]
+    fn test_running_median() {
+        let mut median = RunningMedian::new();
+        assert_eq!(median.median(), None);
+        median.add(5);
+        assert_eq!(median.median(), Some(5.0));
+        median.add(2);
+        assert_eq!(median.median(), Some(3.5));
+        median.add(8);
+        assert_eq!(median.median(), Some(5.0));
+        median.add(1);
+        assert_eq!(median.median(), Some(3.5));
+        assert_eq!(median.len(), 4);
+    }
+
+    #[test]
+    fn test_running_median_stays_balanced() {
+        let mut median = RunningMedian::new();
+        for value in (0..101).rev() {
+            median.add(value);
+            assert!(median.low.len() - median.high.len() <= 1);
+        }
+        assert_eq!(median.median(), Some(50.0));
+    }
+
+    #[test]
+    fn test_kth_largest() {
+        let mut kth = KthLargest::new(3, vec![4, 5, 8, 2]);
+        assert_eq!(*kth.add(3), 4);
+        assert_eq!(*kth.add(5), 5);
+        assert_eq!(*kth.add(10), 5);
+        assert_eq!(*kth.add(9), 8);
+        assert_eq!(*kth.add(4), 8);
+    }
+
+    #[test]
+    fn test_kth_largest_short_initial() {
+        let mut kth = KthLargest::new(2, Vec::new());
+        assert_eq!(*kth.add(7), 7);
+        assert_eq!(*kth.add(3), 3);
+        assert_eq!(*kth.add(5), 5);
+    }
+
+    #[test]
+    fn test_size_hint_shrinks() {
+        let mut heap: Heap<i32> = vec![3, 1, 2].into_iter().collect();
+        assert_eq!(heap.size_hint(), (3, Some(3)));
+        assert_eq!(ExactSizeIterator::len(&heap.by_ref()), heap.len());
+        heap.next();
+        assert_eq!(heap.size_hint(), (2, Some(2)));
+        assert_eq!(ExactSizeIterator::len(&heap), 2);
+        heap.next();
+        heap.next();
+        assert_eq!(heap.size_hint(), (0, Some(0)));
+        assert_eq!(heap.next(), None);
+        assert_eq!(heap.next(), None);
+    }
+
+    #[test]
+    fn test_std_round_trip() {
+        let mut bh = BinaryHeap::new();
+        for value in [4, 9, 1, 7] {
+            bh.push(value);
+        }
+        let heap = Heap::from_std(bh);
+        assert_eq!(heap.peek(), Some(&9));
+        let bh = heap.into_std();
+        assert_eq!(bh.into_sorted_vec(), vec![1, 4, 7, 9]);
+    }
+
+    #[test]
+    fn test_into_std_from_min_heap() {
+        let heap: Heap<i32> = vec![3, 8, 5].into_iter().collect();
+        let mut bh = heap.into_std();
+        assert_eq!(bh.pop(), Some(8));
+        let back = Heap::from_std(bh);
+        assert_eq!(back.into_sorted_vec(), vec![5, 3]);
+    }
+
+    #[test]
+    fn test_add_all_existing_heap() {
+        for old in 0..20 {
+            for new in 0..20 {
+                let mut heap: Heap<i32> = (0..old).map(|x| (x * 7) % 20).collect();
+                heap.add_all((0..new).map(|x| 40 - x));
+                assert!(heap.is_valid(), "old = {}, new = {}", old, new);
+                let mut expected: Vec<i32> = (0..old).map(|x| (x * 7) % 20).collect();
+                expected.extend((0..new).map(|x| 40 - x));
+                expected.sort();
+                assert_eq!(heap.into_sorted_vec(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_add_all_fewer_comparisons_than_add() {
+        use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+        fn counting_heap(counter: &Arc<AtomicUsize>) -> Heap<u32> {
+            let counter = Arc::clone(counter);
+            Heap::new_by(move |a, b| {
+                counter.fetch_add(1, AtomicOrdering::Relaxed);
+                a < b
+            })
+        }
+
+        let values: Vec<u32> = (0..10000).rev().collect();
+        let one_by_one = Arc::new(AtomicUsize::new(0));
+        let mut heap = counting_heap(&one_by_one);
+        for &value in &values {
+            heap.add(value);
+        }
+        let batched = Arc::new(AtomicUsize::new(0));
+        let mut heap = counting_heap(&batched);
+        heap.add_all(values);
+        assert!(batched.load(AtomicOrdering::Relaxed) < one_by_one.load(AtomicOrdering::Relaxed));
+        assert_eq!(heap.pop(), Some(0));
+    }
+
+    #[test]
+    fn test_is_valid_detects_broken_layout() {
+        let mut heap: Heap<i32> = vec![1, 2, 3, 4, 5].into_iter().collect();
+        assert!(heap.is_valid());
+        // from_sorted_vec would reject this in debug builds, so corrupt the
+        // array directly
+        heap.items.swap(0, 4);
+        assert!(!heap.is_valid());
+        assert!(MinHeap::new::<i32>().is_valid());
+    }
+
+    #[test]
+    fn test_is_valid_random_operations() {
+        let mut heap = MinHeap::new();
+        for (step, value) in pseudo_random(500, 7).into_iter().enumerate() {
+            if step % 3 == 2 {
+                heap.pop();
+            } else {
+                heap.add(value);
+            }
+            assert!(heap.is_valid(), "invalid after step {}", step);
+        }
+    }
+
+    #[test]
+    fn test_non_default_elements() {
+        // Deliberately has no Default impl
+        #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+        struct Task {
+            priority: u32,
+        }
+
+        let mut heap = MinHeap::new();
+        heap.add(Task { priority: 3 });
+        heap.add(Task { priority: 1 });
+        heap.add(Task { priority: 2 });
+        assert_eq!(heap.next(), Some(Task { priority: 1 }));
+        assert_eq!(MaxHeap::new().pop(), None::<Task>);
+        assert_eq!(heap.into_sorted_vec(), vec![Task { priority: 2 }, Task { priority: 3 }]);
+    }
+
+    #[test]
+    fn test_extract_if_threshold() {
+        let mut heap: Heap<i32> = vec![12, 3, 8, 15, 1, 10, 6].into_iter().collect();
+        let mut above = heap.extract_if(|&x| x > 7);
+        above.sort();
+        assert_eq!(above, vec![8, 10, 12, 15]);
+        assert!(heap.is_valid());
+        assert_eq!(heap.into_sorted_vec(), vec![1, 3, 6]);
+    }
+
+    #[test]
+    fn test_extract_if_nothing_and_everything() {
+        let mut heap: Heap<i32> = vec![4, 2, 9].into_iter().collect();
+        assert!(heap.extract_if(|_| false).is_empty());
+        assert_eq!(heap.len(), 3);
+        assert_eq!(heap.peek(), Some(&2));
+        assert_eq!(heap.extract_if(|_| true).len(), 3);
+        assert!(heap.is_empty());
+        assert!(heap.is_valid());
+    }
+
+    #[test]
+    fn test_nlargest_nsmallest_random() {
+        for seed in 0..10 {
+            let values = pseudo_random(300, seed);
+            let mut sorted = values.clone();
+            sorted.sort();
+            for n in [1, 5, 50] {
+                let smallest: Vec<i64> = sorted.iter().copied().take(n).collect();
+                let largest: Vec<i64> = sorted.iter().rev().copied().take(n).collect();
+                assert_eq!(nsmallest(n, values.clone()), smallest);
+                assert_eq!(nlargest(n, values.clone()), largest);
+            }
+        }
+    }
+
+    #[test]
+    fn test_nlargest_nsmallest_edges() {
+        assert!(nlargest(0, vec![3, 1, 2]).is_empty());
+        assert!(nsmallest(0, vec![3, 1, 2]).is_empty());
+        assert_eq!(nlargest(10, vec![3, 1, 2]), vec![3, 2, 1]);
+        assert_eq!(nsmallest(10, vec![3, 1, 2]), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_peek_nth() {
+        let data = pseudo_random(200, 11);
+        let heap = Heap::from_vec(data.clone(), |a, b| a < b);
+        let mut sorted = data;
+        sorted.sort();
+        assert_eq!(heap.peek_nth(0), heap.peek());
+        for (n, expected) in sorted.iter().enumerate() {
+            assert_eq!(heap.peek_nth(n), Some(expected));
+        }
+        assert_eq!(heap.peek_nth(heap.len()), None);
+        assert_eq!(heap.len(), 200);
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    
     #[test]
-    fn test_empty_heap() {
-        let mut heap = MaxHeap::new::<i32>();
-        assert_eq!(heap.next(), None);
+    fn test_reserve_avoids_reallocation() {
+        let mut heap = MinHeap::new();
+        heap.reserve(100);
+        let capacity = heap.capacity();
+        assert!(capacity >= 100);
+        for i in (0..10).rev() {
+            heap.add(i);
+        }
+        assert_eq!(heap.capacity(), capacity);
+
+        let mut exact: Heap<i32> = MinHeap::new();
+        exact.reserve_exact(7);
+        assert!(exact.capacity() >= 7);
     }
 
     #[test]
-    fn test_min_heap() {
+    fn test_shrink_to_fit() {
+        let mut heap = Heap::from_vec(pseudo_random(1000, 5), |a, b| a < b);
+        let mut sorted = heap.clone().into_sorted_vec();
+        sorted.drain(..995);
+        for _ in 0..995 {
+            heap.pop();
+        }
+        heap.shrink_to_fit();
+        assert!(heap.capacity() < 100);
+        assert!(heap.is_valid());
+        assert_eq!(heap.into_sorted_vec(), sorted);
+    }
+
+    #[test]
+    fn test_reverse_order() {
+        let mut heap = Heap::from_vec(vec![5, 1, 8, 3, 9, 2], |a, b| a < b);
+        assert_eq!(heap.peek(), Some(&1));
+        heap.reverse_order();
+        assert!(heap.is_valid());
+        assert_eq!(heap.next(), Some(9));
+        heap.reverse_order();
+        assert_eq!(heap.into_sorted_vec(), vec![1, 2, 3, 5, 8]);
+    }
+
+    #[test]
+    fn test_get_raw_slot() {
+        let heap = Heap::from_vec(vec![4, 2, 7, 1], |a, b| a < b);
+        assert_eq!(heap.get(0), heap.peek());
+        assert_eq!(heap.get(heap.len()), None);
+        assert!((0..heap.len()).all(|i| heap.get(i).is_some()));
+    }
+
+    #[test]
+    fn test_eq_compares_multisets() {
+        let mut a = MinHeap::new();
+        let mut b = MinHeap::new();
+        for x in [3, 1, 4, 1, 5] {
+            a.add(x);
+        }
+        for x in [5, 1, 1, 4, 3] {
+            b.add(x);
+        }
+        assert_eq!(a, b);
+        assert_eq!(a, Heap::from_vec(vec![1, 1, 3, 4, 5], |x, y| x > y));
+
+        b.pop();
+        b.add(9);
+        assert_ne!(a, b);
+        assert_ne!(a, MinHeap::new());
+    }
+
+    #[test]
+    fn test_into_iter_sorted() {
+        let data = vec![6, 2, 9, 2, 7, 6, 1, 9];
+        let min = Heap::from_vec(data.clone(), |a, b| a < b).into_iter_sorted();
+        assert_eq!(min.len(), data.len());
+        let ascending: Vec<_> = min.collect();
+        assert!(ascending.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(ascending.len(), data.len());
+
+        let descending: Vec<_> = Heap::from_vec(data, |a, b| a > b).into_iter_sorted().collect();
+        assert!(descending.windows(2).all(|w| w[0] >= w[1]));
+        assert_eq!(descending.first(), Some(&9));
+    }
+
+    #[test]
+    fn test_default_is_min_heap() {
+        #[derive(Default)]
+        struct Scheduler {
+            queue: Heap<i32>,
+        }
+
+        assert!(Heap::<i32>::default().is_empty());
+        let mut scheduler = Scheduler::default();
+        scheduler.queue.add(3);
+        scheduler.queue.add(1);
+        scheduler.queue.add(2);
+        assert_eq!(scheduler.queue.pop(), Some(1));
+    }
+
+    #[test]
+    fn test_new_by_key() {
+        #[derive(Debug)]
+        struct Task {
+            name: &'static str,
+            priority: u32,
+        }
+
+        let tasks = [("write", 3), ("test", 1), ("ship", 4), ("review", 2)];
+        let mut heap = Heap::new_by_key(|t: &Task| t.priority);
+        let mut max = Heap::new_by_key_max(|t: &Task| t.priority);
+        for (name, priority) in tasks {
+            heap.add(Task { name, priority });
+            max.add(Task { name, priority });
+        }
+        let names: Vec<_> = heap.map(|t| t.name).collect();
+        assert_eq!(names, vec!["test", "review", "write", "ship"]);
+        assert_eq!(max.pop().map(|t| t.priority), Some(4));
+    }
+
+    #[test]
+    fn test_as_slice() {
+        let heap = Heap::from_vec(pseudo_random(50, 3), |a, b| a < b);
+        assert_eq!(heap.as_slice().len(), heap.len());
+        let mut copy = heap.as_slice().to_vec();
+        copy.sort();
+        assert_eq!(copy, heap.into_sorted_vec());
+    }
+
+    #[test]
+    fn test_hash_ignores_layout() {
+        use std::collections::HashSet;
+
+        let a: Heap<i32> = vec![5, 3, 8, 1].into_iter().collect();
+        let mut b = MinHeap::new();
+        for x in [1, 8, 3, 5] {
+            b.add(x);
+        }
+        let mut set = HashSet::new();
+        set.insert(a);
+        set.insert(b);
+        assert_eq!(set.len(), 1);
+        set.insert(Heap::from_vec(vec![1, 3, 5], |x, y| x < y));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut heap = Heap::from_vec(vec![9, 4, 7, 1, 8, 2, 6, 3, 10, 5], |a, b| a < b);
+        heap.truncate(3);
+        assert_eq!(heap.len(), 3);
+        assert!(heap.is_valid());
+        assert_eq!(heap.clone().into_sorted_vec(), vec![1, 2, 3]);
+
+        heap.truncate(5);
+        assert_eq!(heap.len(), 3);
+        heap.truncate(0);
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    fn test_pop_if() {
+        let mut heap = Heap::from_vec(vec![5, 2, 8], |a, b| a < b);
+        assert_eq!(heap.pop_if(|&top| top < 3), Some(2));
+        assert_eq!(heap.pop_if(|&top| top < 3), None);
+        assert_eq!(heap.len(), 2);
+        assert_eq!(heap.peek(), Some(&5));
+
+        let mut empty = MinHeap::new();
+        assert_eq!(empty.pop_if(|_: &i32| true), None);
+    }
+
+    #[test]
+    fn test_dijkstra() {
+        // 0 -> 1 (4), 0 -> 2 (1), 2 -> 1 (2), 1 -> 3 (1), 2 -> 3 (5); 4 is isolated
+        let adj = vec![
+            vec![(1, 4), (2, 1)],
+            vec![(3, 1)],
+            vec![(1, 2), (3, 5)],
+            vec![],
+            vec![(0, 1)],
+        ];
+        assert_eq!(
+            dijkstra(&adj, 0),
+            vec![Some(0), Some(3), Some(1), Some(4), None]
+        );
+    }
+
+    #[test]
+    fn test_clone_from_reuses_buffer() {
+        let mut dest: Heap<i32> = Heap::with_capacity(256, |a, b| a < b);
+        dest.add(100);
+        let capacity = dest.capacity();
+        let source = Heap::from_vec(vec![7, 3, 5], |a, b| a > b);
+        dest.clone_from(&source);
+        assert_eq!(dest.capacity(), capacity);
+        assert_eq!(dest.len(), 3);
+        assert_eq!(dest.pop(), Some(7));
+    }
+
+    #[test]
+    fn test_new_total_orders_nan_last() {
+        let mut heap = Heap::new_total();
+        for x in [3.5, f64::NAN, -1.0, f64::INFINITY, f64::NAN, 0.0, 2.25] {
+            heap.add(x);
+            assert!(heap.is_valid());
+        }
+        let drained: Vec<f64> = heap.collect();
+        assert_eq!(&drained[..5], &[-1.0, 0.0, 2.25, 3.5, f64::INFINITY]);
+        assert!(drained[5..].iter().all(|x| x.is_nan()));
+    }
+
+    #[test]
+    fn test_first_and_find_last() {
+        let heap = Heap::from_vec(pseudo_random(101, 8), |a, b| a < b);
+        let sorted = heap.clone().into_sorted_vec();
+        assert_eq!(heap.first(), sorted.first());
+        assert_eq!(heap.find_last().as_ref(), sorted.last());
+
+        let single = Heap::from_vec(vec![4], |a, b| a < b);
+        assert_eq!(single.find_last(), Some(4));
+        assert_eq!(MinHeap::new().find_last(), None::<i32>);
+
+        // Equal keys with distinguishable payloads
+        let mut ties = Heap::new_by_key(|t: &(u32, u32)| t.0);
+        for i in 0..30 {
+            ties.add((i % 3, i));
+        }
+        assert_eq!(ties.find_last(), ties.clone().into_sorted_vec().pop());
+    }
+
+    #[test]
+    fn test_from_vec_parallel_matches_sequential() {
+        let data = pseudo_random(100_000, 21);
+        let parallel = Heap::from_vec_parallel(data.clone(), 4);
+        assert!(parallel.is_valid());
+        let sequential = Heap::from_vec(data, |a, b| a < b);
+        assert_eq!(parallel.into_sorted_vec(), sequential.into_sorted_vec());
+
+        let small = Heap::from_vec_parallel(vec![3, 1, 2], 8);
+        assert_eq!(small.into_sorted_vec(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_from_vec_parallel_does_no_extra_work() {
+        use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+        static COMPARISONS: AtomicUsize = AtomicUsize::new(0);
+        #[derive(Clone, PartialEq, Eq)]
+        struct Counted(i64);
+        impl PartialOrd for Counted {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Counted {
+            fn cmp(&self, other: &Self) -> Ordering {
+                COMPARISONS.fetch_add(1, AtomicOrdering::Relaxed);
+                self.0.cmp(&other.0)
+            }
+        }
+
+        let data: Vec<Counted> = pseudo_random(100_000, 35)
+            .into_iter()
+            .map(Counted)
+            .collect();
+        let comparisons = |build: &dyn Fn(Vec<Counted>) -> Heap<Counted>| {
+            COMPARISONS.store(0, AtomicOrdering::Relaxed);
+            let heap = build(data.clone());
+            let used = COMPARISONS.load(AtomicOrdering::Relaxed);
+            assert!(heap.is_valid());
+            used
+        };
+        let sequential = comparisons(&|items| Heap::from_vec(items, |a, b| a < b));
+        let parallel = comparisons(&|items| Heap::from_vec_parallel(items, 4));
+        // The threads split the sequential build's work rather than redoing it
+        assert!(parallel <= sequential + sequential / 20);
+        let fixed = comparisons(&|items| {
+            Heap::from_vec_parallel_with(items, 4, ChunkStrategy::FixedCount(16))
+        });
+        assert!(fixed <= sequential + sequential / 20);
+    }
+
+    #[test]
+    fn test_drain_sorted() {
+        let data = pseudo_random(40, 13);
+        let mut heap = Heap::from_vec(data.clone(), |a, b| a < b);
+        let prefix: Vec<_> = heap.drain_sorted().take(15).collect();
+        assert!(prefix.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(heap.len(), 25);
+        assert!(heap.is_valid());
+        assert!(heap.peek().is_some_and(|top| top >= prefix.last().unwrap()));
+
+        let rest: Vec<_> = heap.drain_sorted().collect();
+        assert!(heap.is_empty());
+        let mut sorted = data;
+        sorted.sort();
+        assert_eq!([prefix, rest].concat(), sorted);
+    }
+
+    #[test]
+    fn test_zero_sized_elements() {
         let mut heap = MinHeap::new();
-        heap.add(4);
-        heap.add(2);
-        heap.add(9);
-        heap.add(11);
+        for i in 1..=5 {
+            heap.add(());
+            assert_eq!(heap.len(), i);
+        }
+        assert!(heap.is_valid());
+        assert!(heap.remove(&()));
+        heap.add(());
+        for i in (0..5).rev() {
+            assert_eq!(heap.pop(), Some(()));
+            assert_eq!(heap.len(), i);
+        }
+        assert_eq!(heap.pop(), None);
+        heap.shrink_to_fit();
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    fn test_count_matching() {
+        let heap = Heap::from_vec(vec![1, 2, 3, 4, 5, 6, 7], |a, b| a < b);
+        assert_eq!(heap.count_matching(|x| x % 2 == 0), 3);
+        assert_eq!(heap.count_matching(|&x| x > 0), heap.len());
+        assert_eq!(MinHeap::new().count_matching(|_: &i32| true), 0);
+    }
+
+    #[test]
+    fn test_position() {
+        let heap = Heap::from_vec(vec![8, 3, 5, 1], |a, b| a < b);
+        assert_eq!(heap.position(&1), Some(0));
+        let leaf = *heap.get(heap.len() - 1).unwrap();
+        assert_eq!(heap.position(&leaf), Some(heap.len() - 1));
+        assert_eq!(heap.position(&42), None);
+
+        // Through `&mut`, plain method syntax would pick `Iterator::position`
+        fn find(heap: &mut Heap<i32>, value: i32) -> Option<usize> {
+            Heap::position(heap, &value)
+        }
+        let mut heap = heap;
+        assert_eq!(find(&mut heap, 1), Some(0));
         assert_eq!(heap.len(), 4);
-        assert_eq!(heap.next(), Some(2));
-        assert_eq!(heap.next(), Some(4));
-        assert_eq!(heap.next(), Some(9));
-        heap.add(1);
-        assert_eq!(heap.next(), Some(1));
     }
 
     #[test]
-    fn test_max_heap() {
-        let mut heap = MaxHeap::new();
-        heap.add(4);
-        heap.add(2);
+    fn test_min_max_heap() {
+        let data = pseudo_random(300, 17);
+        let mut heap = MinMaxHeap::new();
+        for &x in &data {
+            heap.push(x);
+        }
+        let mut sorted = data;
+        sorted.sort();
+        let mut expected: std::collections::VecDeque<_> = sorted.into();
+        assert_eq!(heap.len(), expected.len());
+        let mut from_min = true;
+        while !expected.is_empty() {
+            assert_eq!(heap.peek_min(), expected.front());
+            assert_eq!(heap.peek_max(), expected.back());
+            if from_min {
+                assert_eq!(heap.pop_min(), expected.pop_front());
+            } else {
+                assert_eq!(heap.pop_max(), expected.pop_back());
+            }
+            from_min = !from_min;
+        }
+        assert!(heap.is_empty());
+        assert_eq!(heap.pop_min(), None);
+        assert_eq!(heap.pop_max(), None);
+    }
+
+    #[test]
+    fn test_last_matches_full_drain() {
+        for seed in 0..5 {
+            let data = pseudo_random(64 + seed as usize, seed);
+            let min = Heap::from_vec(data.clone(), |a, b| a < b);
+            let max = Heap::from_vec(data.clone(), |a, b| a > b);
+            assert_eq!(min.clone().last(), min.into_sorted_vec().pop());
+            assert_eq!(max.clone().last(), max.into_sorted_vec().pop());
+        }
+        assert_eq!(MinHeap::new().last(), None::<i32>);
+    }
+
+    #[test]
+    fn test_max_element_with_ties() {
+        for seed in 0..20 {
+            let mut heap = Heap::new_by_key(|t: &(i64, i64)| t.0);
+            for (i, x) in pseudo_random(50, seed).into_iter().enumerate() {
+                heap.add((x % 4, i as i64));
+            }
+            let drained = heap.clone().into_sorted_vec();
+            // `last` drains like `next`, so it ends on the same payload
+            assert_eq!(heap.clone().last().as_ref(), drained.last());
+            // `max_element` only promises a lowest-ranking key
+            assert_eq!(heap.max_element().map(|t| t.0), drained.last().map(|t| t.0));
+        }
+        assert_eq!(MinHeap::new().max_element(), None::<&i32>);
+    }
+
+    #[test]
+    fn test_drain_dedup() {
+        let data: Vec<i64> = pseudo_random(200, 4).iter().map(|x| x % 10).collect();
+        let mut heap = Heap::from_vec(data.clone(), |a, b| a < b);
+        let unique: Vec<_> = heap.drain_dedup().collect();
+        let mut expected = data;
+        expected.sort();
+        expected.dedup();
+        assert_eq!(unique, expected);
+        assert!(heap.is_empty());
+
+        let mut max = Heap::from_vec(vec![2, 2, 3, 1, 3, 3], |a, b| a > b);
+        assert_eq!(max.drain_dedup().collect::<Vec<_>>(), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_replace_root_matches_replace() {
+        let mut a = Heap::from_vec(pseudo_random(30, 6), |a, b| a < b);
+        let mut b = a.clone();
+        for x in pseudo_random(30, 7) {
+            assert_eq!(Some(a.replace_root(x)), b.replace(x));
+        }
+        assert!(a.is_valid());
+        assert_eq!(a.into_sorted_vec(), b.into_sorted_vec());
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "empty heap")]
+    fn test_replace_root_on_empty_panics() {
+        MinHeap::new().replace_root(1);
+    }
+
+    #[test]
+    fn test_into_vec() {
+        let data = pseudo_random(40, 9);
+        let heap = Heap::from_vec(data.clone(), |a, b| a < b);
+        let raw = heap.clone().into_vec();
+        assert_eq!(raw, heap.as_slice());
+        let mut multiset = raw.clone();
+        multiset.sort();
+        let mut expected = data;
+        expected.sort();
+        assert_eq!(multiset, expected);
+        assert_eq!(Heap::from_vec(raw, |a, b| a < b).into_sorted_vec(), expected);
+    }
+
+    #[test]
+    fn test_meld() {
+        let a = Heap::from_vec(vec![9, 1, 5], |a, b| a < b);
+        let b = Heap::from_vec(vec![4, 8, 2, 7], |a, b| a > b);
+        let melded = Heap::meld(a, b);
+        assert!(melded.is_valid());
+        assert_eq!(melded.into_sorted_vec(), vec![1, 2, 4, 5, 7, 8, 9]);
+
+        let empty = MinHeap::new();
+        let other = Heap::from_vec(vec![3, 1, 2], |a, b| a < b);
+        assert_eq!(Heap::meld(empty, other).into_sorted_vec(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_to_tree_string() {
+        let mut heap = MinHeap::new();
+        for x in [5, 3, 4, 1, 2] {
+            heap.add(x);
+        }
+        // add() leaves the layout [1, 2, 4, 5, 3]
+        assert_eq!(heap.to_tree_string(), "1\n  2\n    5\n    3\n  4\n");
+        assert_eq!(Heap::<i32>::new_min().to_tree_string(), "");
+    }
+
+    #[test]
+    fn test_try_heap_leaves_heap_unchanged_on_error() {
+        use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+
+        // Stands in for a lookup service the comparator depends on
+        static OFFLINE: AtomicBool = AtomicBool::new(false);
+        fn cmp(a: &i32, b: &i32) -> Result<bool, String> {
+            if *a == 13 || *b == 13 {
+                Err("13 cannot be compared".to_string())
+            } else if OFFLINE.load(AtomicOrdering::SeqCst) {
+                Err("lookup offline".to_string())
+            } else {
+                Ok(a < b)
+            }
+        }
+
+        let mut heap = Heap::new_try(cmp);
+        for x in pseudo_random(20, 3) {
+            heap.try_add(x as i32 % 100).unwrap();
+        }
+        let before = heap.items.clone();
+        assert!(heap.try_add(13).is_err());
+        assert_eq!(heap.items, before);
+
+        OFFLINE.store(true, AtomicOrdering::SeqCst);
+        assert_eq!(heap.try_pop(), Err("lookup offline".to_string()));
+        assert_eq!(heap.items, before);
+        OFFLINE.store(false, AtomicOrdering::SeqCst);
+
+        let mut drained = Vec::new();
+        while let Some(x) = heap.try_pop().unwrap() {
+            drained.push(x);
+        }
+        let mut expected = before;
+        expected.sort();
+        assert_eq!(drained, expected);
+    }
+
+    #[test]
+    fn test_shrink_to() {
+        let mut heap = Heap::from_vec(pseudo_random(1000, 10), |a, b| a < b);
+        let mut expected = heap.clone().into_sorted_vec();
+        expected.drain(..990);
+        for _ in 0..990 {
+            heap.pop();
+        }
+        let before = heap.capacity();
+        heap.shrink_to(64);
+        assert!(heap.capacity() >= 64 && heap.capacity() < before);
+        assert_eq!(heap.into_sorted_vec(), expected);
+    }
+
+    #[test]
+    fn test_extend_reserves_from_size_hint_up_front() {
+        // Hints 100 items only until the first one is taken. `Vec::extend`
+        // consults the hint after that, so only an up-front reserve sees it.
+        struct Probe {
+            items: std::ops::Range<i32>,
+            started: bool,
+        }
+
+        impl Iterator for Probe {
+            type Item = i32;
+
+            fn next(&mut self) -> Option<i32> {
+                self.started = true;
+                self.items.next()
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (if self.started { 0 } else { 100 }, None)
+            }
+        }
+
+        let mut heap = MinHeap::new();
+        heap.extend(Probe {
+            items: 0..3,
+            started: false,
+        });
+        assert!(heap.capacity() >= 100);
+        assert_eq!(heap.len(), 3);
+    }
+
+    #[test]
+    fn test_peek_two() {
+        let mut heap = MinHeap::new();
+        assert_eq!(heap.peek_two(), (None, None));
+        heap.add(5);
+        assert_eq!(heap.peek_two(), (Some(&5), None));
         heap.add(9);
-        heap.add(11);
-        assert_eq!(heap.len(), 4);
-        assert_eq!(heap.next(), Some(11));
-        assert_eq!(heap.next(), Some(9));
-        assert_eq!(heap.next(), Some(4));
-        heap.add(1);
-        assert_eq!(heap.next(), Some(2));
+        assert_eq!(heap.peek_two(), (Some(&5), Some(&9)));
+        for x in pseudo_random(50, 12) {
+            heap.add(x);
+            assert_eq!(heap.peek_two(), (heap.peek_nth(0), heap.peek_nth(1)));
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_remove_at() {
+        let data = pseudo_random(60, 14);
+        let mut heap = Heap::from_vec(data.clone(), |a, b| a < b);
+        let mut expected = data;
+        expected.sort();
+
+        let top = heap.remove_at(0).unwrap();
+        assert_eq!(top, expected.remove(0));
+        assert!(heap.is_valid());
+
+        let interior = *heap.get(23).unwrap();
+        assert_eq!(heap.remove_at(23), Some(interior));
+        assert!(heap.is_valid());
+        expected.remove(expected.iter().position(|&x| x == interior).unwrap());
+
+        let last = heap.len() - 1;
+        let leaf = *heap.get(last).unwrap();
+        assert_eq!(heap.remove_at(last), Some(leaf));
+        assert!(heap.is_valid());
+        expected.remove(expected.iter().position(|&x| x == leaf).unwrap());
+
+        assert_eq!(heap.remove_at(heap.len()), None);
+        assert_eq!(heap.into_sorted_vec(), expected);
+    }
+
+    #[test]
+    fn test_iter_mut_rebuilds_on_drop() {
+        let data = pseudo_random(50, 15);
+        let mut heap = Heap::from_vec(data.clone(), |a, b| a < b);
+        for x in &mut heap.iter_mut() {
+            *x *= 2;
+        }
+        assert!(heap.is_valid());
+
+        // Negating reverses the order, so the rebuild has real work to do
+        heap.iter_mut().iter_mut().for_each(|x| *x = -*x);
+        assert!(heap.is_valid());
+
+        let mut expected: Vec<_> = data.iter().map(|x| -2 * x).collect();
+        expected.sort();
+        assert_eq!(heap.into_sorted_vec(), expected);
+    }
+
+    #[test]
+    fn test_drain_sorted_by_key() {
+        #[derive(Debug, PartialEq)]
+        struct Job {
+            id: u32,
+            priority: u32,
+        }
+
+        let mut heap = Heap::new(|a: &Job, b: &Job| a.priority > b.priority);
+        for (id, priority) in [(3, 1), (1, 7), (4, 5), (2, 9)] {
+            heap.add(Job { id, priority });
+        }
+        assert_eq!(heap.peek().map(|job| job.id), Some(2));
+        let jobs = heap.drain_sorted_by_key(|job| job.id);
+        let ids: Vec<_> = jobs.iter().map(|job| job.id).collect();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+        assert!(heap.is_empty());
+        heap.add(Job { id: 5, priority: 0 });
+        assert_eq!(heap.len(), 1);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "inconsistent comparator")]
+    fn test_broken_comparator_is_reported() {
+        Heap::from_vec(vec![3, 1, 2], |_, _| true);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "ranks above itself")]
+    fn test_non_strict_comparator_is_reported() {
+        let mut heap = Heap::new(|a: &i32, b: &i32| a <= b);
+        heap.add_all(vec![3, 1, 2]);
+    }
+
+    #[test]
+    fn test_borrowing_for_loop() {
+        let data = vec![6, 2, 9, 2, 4];
+        let heap = Heap::from_vec(data.clone(), |a, b| a < b);
+        let mut seen = Vec::new();
+        for x in &heap {
+            seen.push(*x);
+        }
+        seen.sort();
+        let mut expected = data;
+        expected.sort();
+        assert_eq!(seen, expected);
+        assert_eq!(heap.len(), 5);
+    }
+
+    #[test]
+    fn test_consistency_through_mixed_operations() {
+        let mut heap = MinHeap::new();
+        heap.check_consistency();
+        for (step, x) in pseudo_random(200, 16).into_iter().enumerate() {
+            if step % 3 == 2 {
+                heap.pop();
+            } else {
+                heap.add(x);
+            }
+            heap.check_consistency();
+        }
+        heap.retain(|x| x % 2 == 0);
+        heap.check_consistency();
+        heap.add_all(pseudo_random(20, 17));
+        heap.check_consistency();
+        let mut other = heap.split_off(10);
+        other.check_consistency();
+        heap.append(&mut other);
+        heap.check_consistency();
+        other.check_consistency();
+        heap.truncate(5);
+        heap.check_consistency();
+        heap.clear();
+        heap.check_consistency();
+    }
+
+    #[test]
+    fn test_heapify_in_place() {
+        let less = |a: &i64, b: &i64| a < b;
+        let mut items = pseudo_random(75, 18);
+        let mut expected = items.clone();
+        expected.sort();
+        heapify(&mut items, less);
+        assert!((1..items.len()).all(|idx| !less(&items[idx], &items[(idx - 1) / 2])));
+
+        let mut extracted = Vec::new();
+        while !items.is_empty() {
+            extracted.push(items.swap_remove(0));
+            sift_down(&mut items, 0, &less);
+        }
+        assert_eq!(extracted, expected);
+    }
+
+    #[test]
+    fn test_heap_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let threshold = 10;
+        let mut by_closure =
+            Heap::new_by(move |a: &i32, b: &i32| (a - threshold).abs() < (b - threshold).abs());
+        by_closure.reverse_order();
+        assert_send_sync(&by_closure);
+        assert_send_sync(&Heap::<i32>::new_min());
+        assert_send_sync(&Heap::<i32>::new_by_key(|x| -x));
+
+        let shared = Arc::new(Mutex::new(by_closure));
+        let worker = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || shared.lock().unwrap().add(3))
+        };
+        worker.join().unwrap();
+        assert_eq!(shared.lock().unwrap().peek(), Some(&3));
+    }
+
+    #[test]
+    fn test_pop_n() {
+        let mut heap = Heap::from_vec(vec![8, 3, 6, 1, 9], |a, b| a < b);
+        assert_eq!(heap.pop_n(3), vec![1, 3, 6]);
+        assert_eq!(heap.len(), 2);
+        assert!(heap.is_valid());
+        assert_eq!(heap.pop_n(10), vec![8, 9]);
+        assert!(heap.is_empty());
+        assert!(heap.pop_n(1).is_empty());
+    }
+
+    #[test]
+    fn test_from_vec_parallel_chunk_strategies() {
+        let data = pseudo_random(50_000, 33);
+        let expected = Heap::from_vec(data.clone(), |a, b| a < b).into_sorted_vec();
+        for strategy in [
+            ChunkStrategy::EvenBySize,
+            ChunkStrategy::FixedCount(3),
+            ChunkStrategy::FixedCount(16),
+        ] {
+            let heap = Heap::from_vec_parallel_with(data.clone(), 4, strategy);
+            assert!(heap.is_valid());
+            assert_eq!(heap.into_sorted_vec(), expected);
+        }
+    }
+
+    #[test]
+    fn test_from_vec_parallel_single_chunk_is_sequential() {
+        let data = pseudo_random(20_000, 34);
+        let parallel = Heap::from_vec_parallel_with(data.clone(), 4, ChunkStrategy::FixedCount(1));
+        let sequential = Heap::from_vec(data, |a, b| a < b);
+        assert_eq!(parallel.as_slice(), sequential.as_slice());
+    }
+
+    #[test]
+    fn test_retain_removed() {
+        let mut heap = Heap::from_vec(vec![7, 2, 9, 4, 1, 8, 3], |a, b| a > b);
+        let removed = heap.retain_removed(|&x| x >= 5);
+        assert_eq!(removed, vec![4, 3, 2, 1]);
+        assert!(heap.is_valid());
+        assert_eq!(heap.into_sorted_vec(), vec![9, 8, 7]);
+    }
+
+    #[test]
+    fn test_singleton_and_pair() {
+        let single = Heap::singleton(5, |a, b| a < b);
+        assert_eq!(single.len(), 1);
+        assert_eq!(single.peek(), Some(&5));
+
+        let min = Heap::pair(9, 2, |a, b| a < b);
+        assert_eq!(min.into_sorted_vec(), vec![2, 9]);
+        let max = Heap::pair(2, 9, |a, b| a > b);
+        assert_eq!(max.into_sorted_vec(), vec![9, 2]);
+    }
+}